@@ -9,9 +9,13 @@ cargo run --example diarize_whisper sam_altman.wav
 
 use eyre::{bail, Result};
 use sherpa_rs::{
-    embedding_manager, speaker_id,
+    diarize::{
+        attribute::{attribute_words, merge_tokens, timed_tokens, AttributeConfig},
+        diarize, DiarizeConfig,
+    },
+    speaker_id,
     transcribe::whisper::WhisperRecognizer,
-    vad::{Vad, VadConfig},
+    vad::{postprocess::PostProcessor, Vad, VadConfig},
 };
 
 fn read_audio_file(path: &str) -> Result<(i32, Vec<f32>)> {
@@ -35,12 +39,7 @@ fn read_audio_file(path: &str) -> Result<(i32, Vec<f32>)> {
 fn main() -> Result<()> {
     // Read audio data from the file
     let path = std::env::args().nth(1).expect("Missing file path argument");
-    let (sample_rate, mut samples) = read_audio_file(&path)?;
-
-    // Pad with 3 seconds of slience so vad will able to detect stop
-    for _ in 0..3 * sample_rate {
-        samples.push(0.0);
-    }
+    let (sample_rate, samples) = read_audio_file(&path)?;
 
     let extractor_config = speaker_id::ExtractorConfig::new(
         "nemo_en_speakerverification_speakernet.onnx".into(),
@@ -48,9 +47,7 @@ fn main() -> Result<()> {
         None,
         false,
     );
-    let mut extractor = speaker_id::EmbeddingExtractor::new_from_config(extractor_config).unwrap();
-    let mut embedding_manager =
-        embedding_manager::EmbeddingManager::new(extractor.embedding_size.try_into().unwrap()); // Assuming dimension 512 for embeddings
+    let extractor = speaker_id::EmbeddingExtractor::new_from_config(extractor_config).unwrap();
 
     let mut recognizer = WhisperRecognizer::new(
         "sherpa-onnx-whisper-tiny/tiny-decoder.onnx".into(),
@@ -63,92 +60,41 @@ fn main() -> Result<()> {
         None,
     );
 
-    let mut speaker_counter = 0;
-
-    let vad_model = "silero_vad.onnx".into();
-    let window_size: usize = 512;
-    let config = VadConfig::new(
-        vad_model,
-        0.4,
-        0.4,
-        0.5,
-        sample_rate,
-        window_size.try_into().unwrap(),
-        None,
-        None,
-        Some(false),
-    );
+    let config = VadConfig::builder("silero_vad.onnx")
+        .with_min_silence_duration(0.4)
+        .with_min_speech_duration(0.4)
+        .with_sample_rate(sample_rate)
+        .build()?;
 
     let mut vad = Vad::new_from_config(config, 60.0 * 10.0).unwrap();
-    let mut index = 0;
-    while index + window_size <= samples.len() {
-        let window = &samples[index..index + window_size];
-        vad.accept_waveform(window.to_vec()); // Convert slice to Vec
-        if vad.is_speech() {
-            while !vad.is_empty() {
-                let segment = vad.front();
-                let start_sec = (segment.start as f32) / sample_rate as f32;
-                let duration_sec = (segment.samples.len() as f32) / sample_rate as f32;
-                let transcript = recognizer.transcribe(sample_rate, segment.samples.clone());
-
-                // Compute the speaker embedding
-                let mut embedding =
-                    extractor.compute_speaker_embedding(sample_rate, segment.samples)?;
-                let name = if let Some(speaker_name) = embedding_manager.search(&embedding, 0.4) {
-                    speaker_name
-                } else {
-                    // Register a new speaker and add the embedding
-                    let name = format!("speaker {}", speaker_counter);
-                    embedding_manager.add(name.clone(), &mut embedding)?;
+    let turns = diarize(&mut vad, &extractor, &samples, &DiarizeConfig::default())?;
+
+    // Transcribe the speech covered by the turns rather than running the VAD again.
+    // Turns of one VAD segment touch, so they are merged back into it.
+    let to_sample = |seconds: f32| ((seconds * sample_rate as f32) as usize).min(samples.len());
+    let ranges = turns
+        .iter()
+        .map(|turn| (to_sample(turn.start), to_sample(turn.end)))
+        .collect();
 
-                    speaker_counter += 1;
-                    name
-                };
-                println!(
-                    "({}) {} | {}s - {}s",
-                    name,
-                    transcript.text,
-                    start_sec,
-                    start_sec + duration_sec
-                );
-                vad.pop();
-            }
-        }
-        index += window_size;
+    // Whisper handles at most 30 seconds, keep some context around each segment
+    let post_processor = PostProcessor::new(0.1, 0.1, 0.3, Some(28.0));
+    let to_seconds = |sample: usize| sample as f32 / sample_rate as f32;
+    let mut tokens = Vec::new();
+    for (start, end) in post_processor.process_ranges(&samples, sample_rate, ranges) {
+        let result = recognizer.transcribe(sample_rate, samples[start..end].to_vec());
+        tokens.extend(timed_tokens(
+            &result.tokens,
+            &result.timestamps,
+            to_seconds(start),
+            to_seconds(end),
+        ));
     }
 
-    if index < samples.len() {
-        let remaining_samples = &samples[index..];
-        vad.accept_waveform(remaining_samples.to_vec());
-        while !vad.is_empty() {
-            let segment = vad.front();
-            let start_sec = (segment.start as f32) / sample_rate as f32;
-            let duration_sec = (segment.samples.len() as f32) / sample_rate as f32;
-            let transcript = recognizer.transcribe(sample_rate, segment.samples.clone());
-
-            // Compute the speaker embedding
-            let mut embedding =
-                extractor.compute_speaker_embedding(sample_rate, segment.samples)?;
-
-            let name = if let Some(speaker_name) = embedding_manager.search(&embedding, 0.4) {
-                speaker_name
-            } else {
-                // Register a new speaker and add the embedding
-                let name = format!("speaker {}", speaker_counter);
-                embedding_manager.add(name.clone(), &mut embedding)?;
-
-                speaker_counter += 1;
-                name
-            };
-            println!(
-                "({}) {} | {}s - {}s",
-                name,
-                transcript.text,
-                start_sec,
-                start_sec + duration_sec
-            );
-            vad.pop();
-        }
+    // A speaker change in the middle of a segment splits its text
+    let words = merge_tokens(&tokens);
+    for utterance in attribute_words(&words, &turns, &AttributeConfig::default()) {
+        println!("{}", utterance);
     }
     Ok(())
 }
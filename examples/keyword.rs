@@ -1,7 +1,7 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{FromSample, Sample, SampleRate};
+use sherpa_rs::online::driver::{Event, OnlineDriver};
 use sherpa_rs::online::stream::keyword_spotter::KeywordSpottingStream;
-use sherpa_rs::online::stream::OnlineStream;
 use sherpa_rs::online::transducer::Transducer;
 use std::fs::File;
 use std::io::BufWriter;
@@ -50,7 +50,8 @@ fn main() -> Result<(), anyhow::Error> {
         keywords,
         None,
         None,
-    );
+    )
+    .expect("failed to create keyword spotter");
 
     println!("Begin recording...");
     let (recorder, receiver) = std::sync::mpsc::channel();
@@ -72,21 +73,15 @@ fn main() -> Result<(), anyhow::Error> {
     println!("Creating recognizer...");
 
     recognizer(spotter, receiver);
+    Ok(())
 }
 
-fn recognizer(mut online_rec: KeywordSpottingStream, receiver: Receiver<Vec<f32>>) -> ! {
+fn recognizer(online_rec: KeywordSpottingStream, receiver: Receiver<Vec<f32>>) {
     println!("Started!");
-    loop {
-        let samples = receiver.recv().unwrap();
-        online_rec.accept_waveform(16000, samples);
-
-        while online_rec.is_ready() {
-            online_rec.decode_stream();
-        }
-
-        let result = online_rec.get_result();
-        if !result.is_empty() {
-            println!("{}", result.to_lowercase());
+    let driver = OnlineDriver::new(online_rec, 16000);
+    for event in driver.events(receiver.iter()) {
+        if let Event::KeywordDetected(utterance) = event {
+            println!("{}", utterance.text.to_lowercase());
         }
     }
 }
@@ -1,8 +1,8 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{FromSample, Sample, SampleRate};
+use sherpa_rs::online::driver::{Event, OnlineDriver};
 use sherpa_rs::online::paraformer::Paraformer;
 use sherpa_rs::online::stream::recognizer::{RecognizerStream, Search};
-use sherpa_rs::online::stream::OnlineStream;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
@@ -45,7 +45,8 @@ fn main() -> Result<(), anyhow::Error> {
         None,
         None,
         None,
-    );
+    )
+    .unwrap();
 
     println!("Begin recording...");
     let (recorder, receiver) = std::sync::mpsc::channel();
@@ -67,34 +68,20 @@ fn main() -> Result<(), anyhow::Error> {
     println!("Creating recognizer...");
 
     recognizer(online_rec, receiver);
+    Ok(())
 }
 
-fn recognizer(mut online_rec: RecognizerStream, receiver: Receiver<Vec<f32>>) -> ! {
-    let mut last_text = String::new();
-    let mut segment_index = 0;
-    println!("current segment: {}", segment_index);
-    loop {
-        let samples = receiver.recv().unwrap();
-        online_rec.accept_waveform(16000, samples);
-
-        while online_rec.is_ready() {
-            online_rec.decode_stream();
-        }
-
-        let result = online_rec.get_result();
-        if !result.is_empty() && last_text != result {
-            last_text = result.clone();
-            println!("\t{}", result.to_lowercase());
-        }
-        if online_rec.is_endpoint() {
-            if !result.is_empty() {
-                let result = online_rec.get_result();
-                last_text = result.clone();
-                println!("final result:{}", result.to_lowercase());
-                segment_index += 1;
-                println!("current segment: {}", segment_index);
+fn recognizer(online_rec: RecognizerStream, receiver: Receiver<Vec<f32>>) {
+    println!("current segment: 0");
+    let driver = OnlineDriver::new(online_rec, 16000);
+    for event in driver.events(receiver.iter()) {
+        match event {
+            Event::Partial(utterance) => println!("\t{}", utterance.text.to_lowercase()),
+            Event::Final(utterance) => {
+                println!("final result:{}", utterance.text.to_lowercase());
+                println!("current segment: {}", utterance.segment + 1);
             }
-            online_rec.reset();
+            Event::KeywordDetected(_) => {}
         }
     }
 }
@@ -54,7 +54,7 @@ fn main() -> Result<()> {
     }
 
     // Create the embedding manager
-    let mut embedding_manager =
+    let embedding_manager =
         embedding_manager::EmbeddingManager::new(extractor.embedding_size.try_into().unwrap()); // Assuming dimension 512 for embeddings
 
     // Map to store speakers and their corresponding files
@@ -63,7 +63,7 @@ fn main() -> Result<()> {
 
     // Process each embedding and identify speakers
     for (file, embedding) in &embeddings {
-        if let Some(speaker_name) = embedding_manager.search(embedding, 0.5) {
+        if let Some(speaker_name) = embedding_manager.search(embedding, 0.5)? {
             // Add file to existing speaker
             speaker_map
                 .entry(speaker_name)
@@ -19,39 +19,33 @@ fn main() -> Result<()> {
         bail!("The sample rate must be 16000.");
     }
 
-    let mut samples: Vec<f32> = reader
+    let samples: Vec<f32> = reader
         .samples::<i16>()
         .map(|s| s.unwrap() as f32 / i16::MAX as f32)
         .collect();
 
-    let model = "silero_vad.onnx".into();
-    let window_size: usize = 512;
-    let config = VadConfig::new(
-        model,
-        0.5,
-        0.5,
-        0.5,
-        sample_rate,
-        window_size.try_into().unwrap(),
-        None,
-        None,
-        Some(true),
-    );
+    let config = VadConfig::builder("silero_vad.onnx")
+        .with_min_speech_duration(0.5)
+        .with_sample_rate(sample_rate)
+        .with_debug(true)
+        .build()?;
 
     let mut vad = Vad::new_from_config(config, 3.0).unwrap();
-    while samples.len() > window_size {
-        let window = &samples[..window_size];
-        vad.accept_waveform(window.to_vec()); // Convert slice to Vec
-        if vad.is_speech() {
-            while !vad.is_empty() {
-                let segment = vad.front();
-                let start_sec = (segment.start as f32) / sample_rate as f32;
-                let duration_sec = (segment.samples.len() as f32) / sample_rate as f32;
-                println!("start={}s duration={}s", start_sec, duration_sec);
-                vad.pop();
-            }
-        }
-        samples = samples[window_size..].to_vec(); // Move the remaining samples to the next iteration
+    for chunk in samples.chunks(sample_rate as usize) {
+        vad.accept_waveform(chunk);
+        print_segments(&mut vad);
     }
+    vad.flush();
+    print_segments(&mut vad);
     Ok(())
 }
+
+fn print_segments(vad: &mut Vad) {
+    for segment in vad.drain() {
+        println!(
+            "start={}s duration={}s",
+            segment.start_sec(),
+            segment.duration_sec()
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Speaker-attributed transcripts from ASR timestamps and speaker turns.
+//!
+//! Each word goes to the speaker whose turns overlap it most, so a turn change
+//! in the middle of an ASR segment splits the text where it happens. Consecutive
+//! words of one speaker are then regrouped into utterances.
+//!
+//! ```ignore
+//! let result = recognizer.transcribe(sample_rate, segment.samples);
+//! let tokens = timed_tokens(&result.tokens, &result.timestamps, segment.start_sec, segment.end_sec);
+//! let words = merge_tokens(&tokens);
+//! for utterance in attribute_words(&words, &turns, &AttributeConfig::default()) {
+//!     println!("{}", utterance);
+//! }
+//! ```
+
+use std::fmt;
+
+use super::SpeakerTurn;
+use crate::text2token::is_cjk;
+
+/// Tokens further apart than this many seconds are never joined into one word.
+const MAX_WORD_GAP: f32 = 0.5;
+
+/// A word or token recognized between `start` and `end` seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedWord {
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeConfig {
+    /// Words overlapping no turn go to the closest turn within this many seconds.
+    pub max_distance: f32,
+    /// A pause longer than this many seconds starts a new utterance even if the
+    /// speaker doesn't change.
+    pub max_pause: f32,
+}
+
+impl Default for AttributeConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: 0.5,
+            max_pause: 2.0,
+        }
+    }
+}
+
+/// Consecutive words of one speaker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utterance {
+    /// `None` for words too far from every turn.
+    pub speaker: Option<String>,
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+    pub words: Vec<TimedWord>,
+}
+
+impl fmt::Display for Utterance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{} - {}] {}: {}",
+            timestamp(self.start),
+            timestamp(self.end),
+            self.speaker.as_deref().unwrap_or("unknown"),
+            self.text
+        )
+    }
+}
+
+/// Place the tokens of a recognizer result on the timeline of the audio it
+/// recognized, which runs from `start` to `end` seconds.
+///
+/// `timestamps` are token starts relative to `start`, and each token lasts until
+/// the next one. Without timestamps, e.g. from whisper, the time is shared out in
+/// proportion to the length of each token.
+pub fn timed_tokens(tokens: &[String], timestamps: &[f32], start: f32, end: f32) -> Vec<TimedWord> {
+    let starts: Vec<f32> = if timestamps.len() == tokens.len() {
+        timestamps.iter().map(|t| start + t).collect()
+    } else {
+        let lengths: Vec<usize> = tokens
+            .iter()
+            .map(|token| token.trim().chars().count().max(1))
+            .collect();
+        let total = lengths.iter().sum::<usize>().max(1) as f32;
+        let mut position = 0;
+        lengths
+            .iter()
+            .map(|length| {
+                let token_start = start + (end - start) * position as f32 / total;
+                position += length;
+                token_start
+            })
+            .collect()
+    };
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, text)| TimedWord {
+            text: text.clone(),
+            start: starts[i],
+            end: starts.get(i + 1).copied().unwrap_or(end).max(starts[i]),
+        })
+        .collect()
+}
+
+/// Join sub-word tokens into words. A token starting with a space or `▁` begins
+/// a new word, as in the token lists of whisper and sherpa-onnx BPE models.
+///
+/// CJK characters, as produced by `cjkchar` models, are words of their own, and
+/// a pause between tokens always ends a word.
+pub fn merge_tokens(tokens: &[TimedWord]) -> Vec<TimedWord> {
+    let mut words: Vec<TimedWord> = Vec::new();
+    for token in tokens {
+        let starts_word = token.text.starts_with([' ', '▁']);
+        let text = token.text.trim_start_matches([' ', '▁']);
+        match words.last_mut() {
+            Some(word)
+                if !starts_word
+                    && !text.starts_with(is_cjk)
+                    && !word.text.ends_with(is_cjk)
+                    && token.start - word.end <= MAX_WORD_GAP =>
+            {
+                word.text.push_str(text);
+                word.end = word.end.max(token.end);
+            }
+            _ if text.is_empty() => {}
+            _ => words.push(TimedWord {
+                text: text.to_string(),
+                start: token.start,
+                end: token.end,
+            }),
+        }
+    }
+    words
+}
+
+/// Speaker of a word: the most overlapping turn, else the closest one within
+/// `max_distance`.
+pub fn word_speaker<'a>(
+    word: &TimedWord,
+    turns: &'a [SpeakerTurn],
+    max_distance: f32,
+) -> Option<&'a str> {
+    let mut best: Option<(&str, f32)> = None;
+    let mut overlaps: Vec<(&str, f32)> = Vec::new();
+    for turn in turns {
+        let overlap = word.end.min(turn.end) - word.start.max(turn.start);
+        if overlap > 0.0 || (overlap == 0.0 && word.start == word.end) {
+            // A speaker may have several turns overlapping one word
+            match overlaps.iter_mut().find(|(s, _)| *s == turn.speaker) {
+                Some((_, total)) => *total += overlap,
+                None => overlaps.push((&turn.speaker, overlap)),
+            }
+        } else {
+            let distance = -overlap;
+            if distance <= max_distance && !matches!(best, Some((_, d)) if d <= distance) {
+                best = Some((&turn.speaker, distance));
+            }
+        }
+    }
+    overlaps
+        .into_iter()
+        .reduce(|a, b| if b.1 > a.1 { b } else { a })
+        .or(best)
+        .map(|(speaker, _)| speaker)
+}
+
+/// Assign every word to a speaker and group consecutive words of the same
+/// speaker into utterances. `words` must be in time order.
+pub fn attribute_words(
+    words: &[TimedWord],
+    turns: &[SpeakerTurn],
+    config: &AttributeConfig,
+) -> Vec<Utterance> {
+    let mut utterances: Vec<Utterance> = Vec::new();
+    for word in words {
+        let speaker = word_speaker(word, turns, config.max_distance);
+        match utterances.last_mut() {
+            Some(utterance)
+                if utterance.speaker.as_deref() == speaker
+                    && word.start - utterance.end <= config.max_pause =>
+            {
+                utterance.end = utterance.end.max(word.end);
+                // CJK tokens carry no word boundaries, and Chinese and Japanese aren't
+                // written with spaces between words anyway
+                if !(utterance.text.ends_with(is_cjk) && word.text.starts_with(is_cjk)) {
+                    utterance.text.push(' ');
+                }
+                utterance.text.push_str(&word.text);
+                utterance.words.push(word.clone());
+            }
+            _ => utterances.push(Utterance {
+                speaker: speaker.map(str::to_string),
+                start: word.start,
+                end: word.end,
+                text: word.text.clone(),
+                words: vec![word.clone()],
+            }),
+        }
+    }
+    utterances
+}
+
+/// `mm:ss.ss`, or `h:mm:ss.ss` past an hour.
+fn timestamp(seconds: f32) -> String {
+    let centis = (seconds.max(0.0) * 100.0).round() as u64;
+    let (hours, minutes) = (centis / 360_000, centis / 6000 % 60);
+    let seconds = (centis % 6000) as f32 / 100.0;
+    if hours > 0 {
+        format!("{}:{:02}:{:05.2}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:05.2}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, start: f32, end: f32) -> TimedWord {
+        TimedWord {
+            text: text.into(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_turn_change_mid_segment() {
+        let tokens = [
+            word(" hel", 0.0, 0.2),
+            word("lo", 0.2, 0.4),
+            word(" there", 0.5, 0.9),
+            word(" hi", 1.0, 1.3),
+            word(" bob", 1.3, 1.6),
+            word(" again", 5.0, 5.4),
+        ];
+        let turns = [
+            SpeakerTurn::new("alice", 0.0, 1.1),
+            SpeakerTurn::new("bob", 1.1, 4.8),
+        ];
+        let words = merge_tokens(&tokens);
+        assert_eq!(words[0], word("hello", 0.0, 0.4));
+
+        let utterances = attribute_words(&words, &turns, &AttributeConfig::default());
+        let summary: Vec<(Option<&str>, &str)> = utterances
+            .iter()
+            .map(|u| (u.speaker.as_deref(), u.text.as_str()))
+            .collect();
+        // "hi" overlaps alice for 0.1s and bob for 0.2s, "again" is close enough to bob
+        // but follows a long pause
+        assert_eq!(
+            summary,
+            vec![
+                (Some("alice"), "hello there"),
+                (Some("bob"), "hi bob"),
+                (Some("bob"), "again"),
+            ]
+        );
+        assert_eq!(
+            utterances[1].to_string(),
+            "[00:01.00 - 00:01.60] bob: hi bob"
+        );
+    }
+
+    #[test]
+    fn test_cjk_tokens() {
+        let tokens = [
+            word("你", 0.0, 0.3),
+            word("好", 0.3, 0.6),
+            word("再", 3.0, 3.3),
+            word("见", 3.3, 3.6),
+        ];
+        let words = merge_tokens(&tokens);
+        assert_eq!(words, tokens);
+
+        let turns = [
+            SpeakerTurn::new("alice", 0.0, 1.0),
+            SpeakerTurn::new("bob", 2.9, 4.0),
+        ];
+        let utterances = attribute_words(&words, &turns, &AttributeConfig::default());
+        let texts: Vec<&str> = utterances.iter().map(|u| u.text.as_str()).collect();
+        assert_eq!(texts, vec!["你好", "再见"]);
+        assert_eq!(utterances[1].speaker.as_deref(), Some("bob"));
+
+        // Hangul syllables are tokens of their own too
+        let tokens = [word("▁안", 0.0, 0.2), word("녕", 0.2, 0.4)];
+        assert_eq!(
+            merge_tokens(&tokens),
+            vec![word("안", 0.0, 0.2), word("녕", 0.2, 0.4)]
+        );
+    }
+
+    #[test]
+    fn test_timed_tokens() {
+        let tokens: Vec<String> = vec![" hi".into(), " there".into()];
+        let timed = timed_tokens(&tokens, &[0.1, 0.5], 10.0, 11.0);
+        assert_eq!(
+            timed,
+            vec![word(" hi", 10.1, 10.5), word(" there", 10.5, 11.0)]
+        );
+
+        // 2 and 5 characters share the second without timestamps
+        let timed = timed_tokens(&tokens, &[], 0.0, 7.0);
+        assert_eq!(timed, vec![word(" hi", 0.0, 2.0), word(" there", 2.0, 7.0)]);
+    }
+}
@@ -0,0 +1,170 @@
+//! Agglomerative clustering of speaker embeddings.
+//!
+//! Clusters are compared by the average cosine distance between their members
+//! (average linkage). The merge tree is built with the nearest-neighbor chain
+//! algorithm, so the result doesn't depend on the order of the embeddings.
+
+use crate::speaker_id::cosine_similarity;
+
+/// When to stop merging clusters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clustering {
+    /// Merge while the average cosine similarity of two clusters is at least this.
+    Threshold(f32),
+    /// Merge until this many speakers are left.
+    NumSpeakers(usize),
+}
+
+/// Assign a cluster to each embedding.
+///
+/// Clusters are numbered in order of their first member.
+pub fn cluster<E: AsRef<[f32]>>(embeddings: &[E], clustering: Clustering) -> Vec<usize> {
+    let n = embeddings.len();
+    let mut merges = linkage(embeddings);
+    merges.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+    let num_merges = match clustering {
+        Clustering::Threshold(threshold) => merges
+            .iter()
+            .take_while(|(_, _, distance)| 1.0 - distance >= threshold)
+            .count(),
+        Clustering::NumSpeakers(speakers) => n.saturating_sub(speakers.max(1)),
+    };
+
+    let mut parent: Vec<usize> = (0..n).collect();
+    for &(a, b, _) in &merges[..num_merges] {
+        let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+        parent[a.max(b)] = a.min(b);
+    }
+
+    let mut root_labels = vec![usize::MAX; n];
+    let mut next = 0;
+    (0..n)
+        .map(|i| {
+            let root = find(&mut parent, i);
+            if root_labels[root] == usize::MAX {
+                root_labels[root] = next;
+                next += 1;
+            }
+            root_labels[root]
+        })
+        .collect()
+}
+
+/// Merges of the average-linkage tree as `(cluster, cluster, distance)`, where
+/// a cluster is named by one of its members.
+fn linkage<E: AsRef<[f32]>>(embeddings: &[E]) -> Vec<(usize, usize, f32)> {
+    let n = embeddings.len();
+    let mut distance = vec![0.0f32; n * n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let d = 1.0 - cosine_similarity(embeddings[i].as_ref(), embeddings[j].as_ref());
+            // Non-finite embeddings are as far from everything as possible
+            let d = if d.is_nan() { f32::INFINITY } else { d };
+            distance[i * n + j] = d;
+            distance[j * n + i] = d;
+        }
+    }
+
+    let mut size = vec![1usize; n];
+    let mut active = vec![true; n];
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+    let mut chain: Vec<usize> = Vec::new();
+
+    for _ in 1..n {
+        if chain.is_empty() {
+            chain.extend(active.iter().position(|&a| a));
+        }
+        // Follow nearest neighbors until two clusters are each other's nearest
+        let (a, b) = loop {
+            let Some(&a) = chain.last() else {
+                return merges;
+            };
+            let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+            // The previous cluster wins ties, so the chain can't cycle
+            let nearest = previous
+                .into_iter()
+                .chain((0..n).filter(|&c| c != a && active[c] && Some(c) != previous))
+                .min_by(|&c, &d| distance[a * n + c].total_cmp(&distance[a * n + d]));
+            let Some(b) = nearest else {
+                return merges;
+            };
+            if Some(b) == previous {
+                chain.truncate(chain.len() - 2);
+                break (a.min(b), a.max(b));
+            }
+            chain.push(b);
+        };
+
+        merges.push((a, b, distance[a * n + b]));
+        // Lance-Williams update for average linkage, the merged cluster is kept at `a`
+        for c in 0..n {
+            if c != a && c != b && active[c] {
+                let d = (size[a] as f32 * distance[a * n + c]
+                    + size[b] as f32 * distance[b * n + c])
+                    / (size[a] + size[b]) as f32;
+                distance[a * n + c] = d;
+                distance[c * n + a] = d;
+            }
+        }
+        size[a] += size[b];
+        active[b] = false;
+    }
+    merges
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embeddings() -> Vec<Vec<f32>> {
+        vec![
+            vec![1.0, 0.1, 0.0],
+            vec![0.0, 1.0, 0.1],
+            vec![0.9, 0.2, 0.0],
+            vec![0.1, 0.9, 0.0],
+            vec![0.0, 0.1, 1.0],
+            vec![1.0, 0.0, 0.1],
+        ]
+    }
+
+    #[test]
+    fn test_threshold_and_count() {
+        let labels = cluster(&embeddings(), Clustering::Threshold(0.8));
+        assert_eq!(labels, vec![0, 1, 0, 1, 2, 0]);
+        let labels = cluster(&embeddings(), Clustering::NumSpeakers(3));
+        assert_eq!(labels, vec![0, 1, 0, 1, 2, 0]);
+        let labels = cluster(&embeddings(), Clustering::NumSpeakers(1));
+        assert_eq!(labels, vec![0; 6]);
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let mut reversed = embeddings();
+        reversed.reverse();
+        let mut labels = cluster(&reversed, Clustering::NumSpeakers(3));
+        labels.reverse();
+        // Same partition, numbered by first appearance in the reversed input
+        assert_eq!(labels, [0, 1, 0, 1, 2, 0].map(|l| [0, 2, 1][l]));
+    }
+
+    #[test]
+    fn test_non_finite_embeddings() {
+        let mut embeddings = embeddings();
+        embeddings[1] = vec![f32::NAN, 0.0, 0.0];
+        embeddings[4] = vec![f32::INFINITY, 1.0, 0.0];
+        let labels = cluster(&embeddings, Clustering::NumSpeakers(2));
+        assert_eq!(labels.len(), 6);
+        let labels = cluster(&embeddings, Clustering::Threshold(0.8));
+        assert_eq!(labels[0], labels[2]);
+        assert_eq!(labels.iter().filter(|&&l| l == labels[1]).count(), 1);
+    }
+}
@@ -0,0 +1,310 @@
+//! Diarization error rate (DER).
+//!
+//! Reference and hypothesis speakers are matched one to one so that their total
+//! overlap is maximal. Within the scored time, every second of reference speech
+//! is then either correct, missed, or attributed to the wrong speaker
+//! (confusion), and hypothesis speech beyond the reference is a false alarm.
+//! Overlapping speakers count once per speaker, as in NIST md-eval.
+
+use std::collections::BTreeMap;
+
+use super::SpeakerTurn;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerConfig {
+    /// Seconds around each reference turn boundary that are not scored.
+    pub collar: f32,
+    /// Don't score regions where the reference has more than one speaker.
+    pub skip_overlap: bool,
+}
+
+impl Default for DerConfig {
+    fn default() -> Self {
+        Self {
+            collar: 0.25,
+            skip_overlap: false,
+        }
+    }
+}
+
+/// Error durations in seconds.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DerReport {
+    /// Scored reference speech, the denominator of the error rates.
+    pub total: f32,
+    pub missed: f32,
+    pub false_alarm: f32,
+    pub confusion: f32,
+    /// Hypothesis speaker matched to each reference speaker.
+    pub mapping: BTreeMap<String, String>,
+}
+
+impl DerReport {
+    pub fn der(&self) -> f32 {
+        self.rate(self.missed + self.false_alarm + self.confusion)
+    }
+
+    pub fn missed_rate(&self) -> f32 {
+        self.rate(self.missed)
+    }
+
+    pub fn false_alarm_rate(&self) -> f32 {
+        self.rate(self.false_alarm)
+    }
+
+    pub fn confusion_rate(&self) -> f32 {
+        self.rate(self.confusion)
+    }
+
+    fn rate(&self, duration: f32) -> f32 {
+        if self.total > 0.0 {
+            duration / self.total
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A stretch of time in which the active speakers don't change.
+struct Region {
+    duration: f64,
+    reference: Vec<usize>,
+    hypothesis: Vec<usize>,
+}
+
+pub fn diarization_error_rate(
+    reference: &[SpeakerTurn],
+    hypothesis: &[SpeakerTurn],
+    config: &DerConfig,
+) -> DerReport {
+    let ref_names = speaker_names(reference);
+    let hyp_names = speaker_names(hypothesis);
+    let regions = scored_regions(reference, hypothesis, &ref_names, &hyp_names, config);
+
+    // Overlap of every reference with every hypothesis speaker
+    let mut overlap = vec![vec![0.0f64; hyp_names.len()]; ref_names.len()];
+    for region in &regions {
+        for &r in &region.reference {
+            for &h in &region.hypothesis {
+                overlap[r][h] += region.duration;
+            }
+        }
+    }
+    let assignment = max_assignment(&overlap);
+
+    let mut report = DerReport::default();
+    let (mut total, mut missed, mut false_alarm, mut confusion) = (0.0, 0.0, 0.0, 0.0);
+    for region in &regions {
+        let n_ref = region.reference.len();
+        let n_hyp = region.hypothesis.len();
+        let correct = region
+            .reference
+            .iter()
+            .filter(|&&r| matches!(assignment[r], Some(h) if region.hypothesis.contains(&h)))
+            .count();
+        total += region.duration * n_ref as f64;
+        missed += region.duration * n_ref.saturating_sub(n_hyp) as f64;
+        false_alarm += region.duration * n_hyp.saturating_sub(n_ref) as f64;
+        confusion += region.duration * (n_ref.min(n_hyp) - correct) as f64;
+    }
+    report.total = total as f32;
+    report.missed = missed as f32;
+    report.false_alarm = false_alarm as f32;
+    report.confusion = confusion as f32;
+    for (r, h) in assignment.iter().enumerate() {
+        if let Some(h) = h {
+            if overlap[r][*h] > 0.0 {
+                report
+                    .mapping
+                    .insert(ref_names[r].clone(), hyp_names[*h].clone());
+            }
+        }
+    }
+    report
+}
+
+fn speaker_names(turns: &[SpeakerTurn]) -> Vec<String> {
+    let mut names: Vec<String> = turns.iter().map(|t| t.speaker.clone()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn scored_regions(
+    reference: &[SpeakerTurn],
+    hypothesis: &[SpeakerTurn],
+    ref_names: &[String],
+    hyp_names: &[String],
+    config: &DerConfig,
+) -> Vec<Region> {
+    let collar = config.collar.max(0.0) as f64;
+    let mut collars: Vec<(f64, f64)> = Vec::new();
+    if collar > 0.0 {
+        for turn in reference {
+            for boundary in [turn.start as f64, turn.end as f64] {
+                collars.push((boundary - collar, boundary + collar));
+            }
+        }
+    }
+
+    let mut points: Vec<f64> = Vec::new();
+    for turn in reference.iter().chain(hypothesis) {
+        points.push(turn.start as f64);
+        points.push(turn.end as f64);
+    }
+    for &(start, end) in &collars {
+        points.push(start);
+        points.push(end);
+    }
+    points.sort_by(|a, b| a.total_cmp(b));
+    points.dedup();
+
+    let active = |turns: &[SpeakerTurn], names: &[String], t: f64| -> Vec<usize> {
+        let mut speakers: Vec<usize> = turns
+            .iter()
+            .filter(|turn| (turn.start as f64) <= t && t < turn.end as f64)
+            .map(|turn| names.binary_search(&turn.speaker).unwrap())
+            .collect();
+        speakers.sort_unstable();
+        speakers.dedup();
+        speakers
+    };
+
+    let mut regions = Vec::new();
+    for pair in points.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let middle = (start + end) / 2.0;
+        if collars.iter().any(|&(s, e)| s <= middle && middle < e) {
+            continue;
+        }
+        let reference = active(reference, ref_names, middle);
+        if config.skip_overlap && reference.len() > 1 {
+            continue;
+        }
+        let hypothesis = active(hypothesis, hyp_names, middle);
+        if reference.is_empty() && hypothesis.is_empty() {
+            continue;
+        }
+        regions.push(Region {
+            duration: end - start,
+            reference,
+            hypothesis,
+        });
+    }
+    regions
+}
+
+/// One-to-one assignment of rows to columns maximizing the total weight
+/// (Hungarian algorithm). `None` for rows left without a column.
+fn max_assignment(weights: &[Vec<f64>]) -> Vec<Option<usize>> {
+    let rows = weights.len();
+    let cols = weights.first().map_or(0, |row| row.len());
+    let n = rows.max(cols);
+    if n == 0 {
+        return vec![None; rows];
+    }
+    let cost = |i: usize, j: usize| -> f64 {
+        if i < rows && j < cols {
+            -weights[i][j]
+        } else {
+            0.0
+        }
+    };
+
+    // Potentials and matching are 1-based, index 0 is a sentinel
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut matched_row = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for i in 1..=n {
+        matched_row[0] = i;
+        let mut j0 = 0;
+        let mut min_value = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = matched_row[j0];
+            let mut delta = f64::INFINITY;
+            let mut j1 = 0;
+            for j in 1..=n {
+                if !used[j] {
+                    let reduced = cost(i0 - 1, j - 1) - u[i0] - v[j];
+                    if reduced < min_value[j] {
+                        min_value[j] = reduced;
+                        way[j] = j0;
+                    }
+                    if min_value[j] < delta {
+                        delta = min_value[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[matched_row[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_value[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if matched_row[j0] == 0 {
+                break;
+            }
+        }
+        while j0 != 0 {
+            let j1 = way[j0];
+            matched_row[j0] = matched_row[j1];
+            j0 = j1;
+        }
+    }
+
+    let mut assignment = vec![None; rows];
+    for (j, &i) in matched_row.iter().enumerate().skip(1) {
+        if i >= 1 && i <= rows && j <= cols {
+            assignment[i - 1] = Some(j - 1);
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_components() {
+        let reference = [
+            SpeakerTurn::new("alice", 0.0, 4.0),
+            SpeakerTurn::new("bob", 4.0, 8.0),
+        ];
+        // Labels are swapped, which costs nothing, but 1s of bob is given to the
+        // other speaker, 1s of speech is missed and 2s are made up.
+        let hypothesis = [
+            SpeakerTurn::new("spk1", 0.0, 4.0),
+            SpeakerTurn::new("spk0", 4.0, 6.0),
+            SpeakerTurn::new("spk1", 6.0, 7.0),
+            SpeakerTurn::new("spk0", 9.0, 11.0),
+        ];
+        let config = DerConfig {
+            collar: 0.0,
+            skip_overlap: false,
+        };
+        let report = diarization_error_rate(&reference, &hypothesis, &config);
+        assert_eq!(report.total, 8.0);
+        assert_eq!(report.confusion, 1.0);
+        assert_eq!(report.missed, 1.0);
+        assert_eq!(report.false_alarm, 2.0);
+        assert_eq!(report.der(), 0.5);
+        assert_eq!(report.mapping["alice"], "spk1");
+
+        // A collar hides the boundaries at 0, 4 and 8
+        let config = DerConfig {
+            collar: 0.5,
+            skip_overlap: false,
+        };
+        let report = diarization_error_rate(&reference, &hypothesis, &config);
+        assert_eq!(report.total, 6.0);
+        assert_eq!(report.missed, 0.5);
+    }
+}
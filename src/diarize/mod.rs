@@ -0,0 +1,361 @@
+//! Offline speaker diarization: who spoke when in a complete recording.
+//!
+//! Speech is found with a [`VoiceActivityDetector`], each speech segment is cut
+//! into overlapping windows, an embedding is computed for every window and the
+//! embeddings are clustered. Every window then votes for the speaker of the part
+//! of the segment closest to its center.
+//!
+//! ```ignore
+//! let turns = diarize(&mut vad, &extractor, &samples, &DiarizeConfig::default())?;
+//! for turn in turns {
+//!     println!("{} {:.2}-{:.2}", turn.speaker, turn.start, turn.end);
+//! }
+//! ```
+
+pub mod attribute;
+pub mod cluster;
+pub mod der;
+pub mod online;
+pub mod rttm;
+
+use eyre::{bail, Result};
+
+use crate::{speaker_id::EmbeddingExtractor, vad::VoiceActivityDetector};
+use cluster::{cluster, Clustering};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiarizeConfig {
+    /// Length of the embedding windows in seconds.
+    pub window: f32,
+    /// Seconds between the starts of consecutive windows.
+    pub step: f32,
+    /// Speech shorter than this many seconds is not labeled.
+    pub min_duration: f32,
+    pub clustering: Clustering,
+    /// Threads computing embeddings.
+    pub num_workers: usize,
+}
+
+impl Default for DiarizeConfig {
+    fn default() -> Self {
+        Self {
+            window: 1.5,
+            step: 0.75,
+            min_duration: 0.3,
+            clustering: Clustering::Threshold(0.5),
+            num_workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// A stretch of speech by one speaker, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeakerTurn {
+    pub speaker: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl SpeakerTurn {
+    pub fn new<T: Into<String>>(speaker: T, start: f32, end: f32) -> Self {
+        Self {
+            speaker: speaker.into(),
+            start,
+            end,
+        }
+    }
+}
+
+/// An embedding window and the part of its segment it labels, in samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    segment: usize,
+    start: usize,
+    end: usize,
+    label_start: usize,
+    label_end: usize,
+}
+
+/// Find the speaker turns of `samples`, recorded at the sample rate of `vad`.
+///
+/// Speakers are named `speaker_0`, `speaker_1`, ... in order of their first turn.
+/// Windows that can't be embedded are left to their neighbors, it is only an
+/// error if none of them can.
+pub fn diarize<V: VoiceActivityDetector>(
+    vad: &mut V,
+    extractor: &EmbeddingExtractor,
+    samples: &[f32],
+    config: &DiarizeConfig,
+) -> Result<Vec<SpeakerTurn>> {
+    let sample_rate = vad.sample_rate();
+    diarize_with(vad, samples, config, |audio| {
+        extractor.embed_batch(sample_rate, audio, config.num_workers)
+    })
+}
+
+fn diarize_with<V, F>(
+    vad: &mut V,
+    samples: &[f32],
+    config: &DiarizeConfig,
+    embed: F,
+) -> Result<Vec<SpeakerTurn>>
+where
+    V: VoiceActivityDetector,
+    F: FnOnce(&[&[f32]]) -> Vec<Result<Vec<f32>>>,
+{
+    let sample_rate = vad.sample_rate();
+    let segments = vad.segment_buffer(samples);
+    let ranges: Vec<(usize, usize)> = segments.iter().map(|s| (s.start, s.end)).collect();
+    let windows = windows(&ranges, sample_rate, config);
+
+    let audio: Vec<&[f32]> = windows
+        .iter()
+        .map(|window| &samples[window.start..window.end])
+        .collect();
+    let (windows, embeddings) = drop_failed(&windows, embed(&audio), sample_rate)?;
+    let labels = cluster(&embeddings, config.clustering);
+    Ok(turns(&windows, &labels, sample_rate))
+}
+
+/// Keep the windows that got an embedding. The part a failed window labels goes
+/// to the previous window of its segment, or to the next one if it is the first.
+fn drop_failed(
+    windows: &[Window],
+    results: Vec<Result<Vec<f32>>>,
+    sample_rate: i32,
+) -> Result<(Vec<Window>, Vec<Vec<f32>>)> {
+    let mut kept: Vec<Window> = Vec::new();
+    let mut embeddings = Vec::new();
+    // Start of the labels of failed windows at the start of a segment
+    let mut orphan: Option<(usize, usize)> = None;
+    let mut last_error = None;
+    for (window, result) in windows.iter().zip(results) {
+        let result = result.and_then(|embedding| {
+            // NaN or infinite values would make every distance meaningless
+            if embedding.iter().all(|v| v.is_finite()) {
+                Ok(embedding)
+            } else {
+                Err(eyre::eyre!("Embedding has non-finite values"))
+            }
+        });
+        match result {
+            Ok(embedding) => {
+                let mut window = *window;
+                if let Some((segment, label_start)) = orphan.take() {
+                    if segment == window.segment {
+                        window.label_start = label_start;
+                    }
+                }
+                kept.push(window);
+                embeddings.push(embedding);
+            }
+            Err(error) => {
+                log::warn!(
+                    "Skipping window at {:.2}s: {}",
+                    window.start as f32 / sample_rate as f32,
+                    error
+                );
+                match kept.last_mut() {
+                    Some(last) if last.segment == window.segment => {
+                        last.label_end = window.label_end
+                    }
+                    _ if !matches!(orphan, Some((segment, _)) if segment == window.segment) => {
+                        orphan = Some((window.segment, window.label_start))
+                    }
+                    _ => {}
+                }
+                last_error = Some(error);
+            }
+        }
+    }
+    if let (true, Some(error)) = (kept.is_empty(), last_error) {
+        bail!(
+            "None of {} windows could be embedded: {}",
+            windows.len(),
+            error
+        )
+    }
+    Ok((kept, embeddings))
+}
+
+/// Cut each speech range into windows of `config.window` seconds every `config.step`
+/// seconds. A range shorter than a window gets a single window covering all of it.
+fn windows(ranges: &[(usize, usize)], sample_rate: i32, config: &DiarizeConfig) -> Vec<Window> {
+    let to_samples = |seconds: f32| (seconds.max(0.0) * sample_rate as f32) as usize;
+    let window_len = to_samples(config.window).max(1);
+    let step = to_samples(config.step).max(1);
+    let min_len = to_samples(config.min_duration);
+
+    let mut windows = Vec::new();
+    for (segment, &(start, end)) in ranges.iter().enumerate() {
+        if end - start < min_len.max(1) {
+            continue;
+        }
+        let mut starts = vec![start];
+        while starts.last().unwrap() + window_len < end {
+            // The last window ends at the end of the segment
+            starts.push((starts.last().unwrap() + step).min(end - window_len));
+        }
+        let centers: Vec<usize> = starts
+            .iter()
+            .map(|&s| (s + (s + window_len).min(end)) / 2)
+            .collect();
+        for (i, &window_start) in starts.iter().enumerate() {
+            // Each window labels the audio closer to its center than to its neighbors'
+            let label_start = if i == 0 {
+                start
+            } else {
+                (centers[i - 1] + centers[i]) / 2
+            };
+            let label_end = if i + 1 == starts.len() {
+                end
+            } else {
+                (centers[i] + centers[i + 1]) / 2
+            };
+            windows.push(Window {
+                segment,
+                start: window_start,
+                end: (window_start + window_len).min(end),
+                label_start,
+                label_end,
+            });
+        }
+    }
+    windows
+}
+
+/// Join neighboring windows of the same segment and speaker into turns.
+fn turns(windows: &[Window], labels: &[usize], sample_rate: i32) -> Vec<SpeakerTurn> {
+    let seconds = |samples: usize| samples as f32 / sample_rate as f32;
+    let mut turns: Vec<(usize, usize, usize, usize)> = Vec::new();
+    for (window, &label) in windows.iter().zip(labels) {
+        match turns.last_mut() {
+            Some(last) if last.0 == window.segment && last.1 == label => {
+                last.3 = window.label_end;
+            }
+            _ => turns.push((window.segment, label, window.label_start, window.label_end)),
+        }
+    }
+    turns
+        .into_iter()
+        .map(|(_, label, start, end)| SpeakerTurn {
+            speaker: format!("speaker_{}", label),
+            start: seconds(start),
+            end: seconds(end),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vad::energy::{tone, EnergyVad, EnergyVadConfig};
+
+    #[test]
+    fn test_windows_cover_segments() {
+        let config = DiarizeConfig {
+            window: 1.0,
+            step: 0.5,
+            min_duration: 0.3,
+            clustering: Clustering::NumSpeakers(2),
+            num_workers: 1,
+        };
+        let windows = windows(&[(0, 25), (30, 32), (40, 45)], 10, &config);
+        let spans: Vec<(usize, usize, usize, usize)> = windows
+            .iter()
+            .map(|w| (w.start, w.end, w.label_start, w.label_end))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (0, 10, 0, 7),
+                (5, 15, 7, 12),
+                (10, 20, 12, 17),
+                (15, 25, 17, 25),
+                (40, 45, 40, 45),
+            ]
+        );
+
+        let turns = turns(&windows, &[0, 0, 1, 1, 0], 10);
+        let spans: Vec<(&str, f32, f32)> = turns
+            .iter()
+            .map(|t| (t.speaker.as_str(), t.start, t.end))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("speaker_0", 0.0, 1.2),
+                ("speaker_1", 1.2, 2.5),
+                ("speaker_0", 4.0, 4.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_short_windows_are_skipped() {
+        let config = DiarizeConfig {
+            window: 1.0,
+            step: 0.5,
+            min_duration: 0.3,
+            clustering: Clustering::Threshold(0.5),
+            num_workers: 1,
+        };
+        let mut samples = tone(2.0, 0.3);
+        samples.extend(vec![0.0; 16000]);
+        samples.extend(tone(0.4, 0.3));
+        let mut vad = EnergyVad::new(EnergyVadConfig::default()).unwrap();
+
+        // Like the extractor, fail on less than half a second of audio
+        let embed = |audio: &[&[f32]]| -> Vec<Result<Vec<f32>>> {
+            audio
+                .iter()
+                .map(|a| match a.len() {
+                    len if len < 8000 => Err(eyre::eyre!("too short")),
+                    _ => Ok(vec![1.0, 0.0]),
+                })
+                .collect()
+        };
+        let turns = diarize_with(&mut vad, &samples, &config, embed).unwrap();
+        let spans: Vec<(&str, f32, f32)> = turns
+            .iter()
+            .map(|t| (t.speaker.as_str(), t.start, t.end))
+            .collect();
+        assert_eq!(spans, vec![("speaker_0", 0.0, 2.0)]);
+
+        let short = tone(0.4, 0.3);
+        let error = diarize_with(&mut vad, &short, &config, embed).unwrap_err();
+        assert!(error.to_string().contains("too short"));
+    }
+
+    #[test]
+    fn test_failed_windows_leave_labels_to_neighbors() {
+        let window = |segment, label_start, label_end| Window {
+            segment,
+            start: label_start,
+            end: label_end,
+            label_start,
+            label_end,
+        };
+        let windows = [
+            window(0, 0, 5),
+            window(0, 5, 10),
+            window(0, 10, 15),
+            window(1, 20, 25),
+            window(1, 25, 30),
+        ];
+        let results = vec![
+            Err(eyre::eyre!("too short")),
+            Ok(vec![1.0]),
+            Ok(vec![f32::NAN]),
+            Ok(vec![1.0]),
+            Err(eyre::eyre!("too short")),
+        ];
+        let (kept, embeddings) = drop_failed(&windows, results, 10).unwrap();
+        let spans: Vec<(usize, usize, usize)> = kept
+            .iter()
+            .map(|w| (w.segment, w.label_start, w.label_end))
+            .collect();
+        assert_eq!(spans, vec![(0, 0, 15), (1, 20, 30)]);
+        assert_eq!(embeddings.len(), 2);
+    }
+}
@@ -0,0 +1,275 @@
+//! Label speakers while audio is still arriving.
+//!
+//! Each speech segment, or each fixed window of audio, is embedded and compared
+//! with the running centroid of every speaker seen so far. A close enough match
+//! moves that centroid towards the new embedding, otherwise a new speaker is
+//! created, up to a limit. Labels never change once given.
+
+use std::sync::Arc;
+
+use eyre::Result;
+
+use super::SpeakerTurn;
+use crate::{
+    speaker_id::{cosine_similarity, EmbeddingExtractor},
+    vad::SpeechSegment,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineTrackerConfig {
+    /// Minimum cosine similarity to a centroid to be assigned to its speaker.
+    pub threshold: f32,
+    /// Weight of a new embedding in its speaker's centroid, between 0 and 1.
+    pub alpha: f32,
+    /// Once reached, audio goes to the closest existing speaker.
+    pub max_speakers: Option<usize>,
+    /// Seconds per window for [`OnlineTracker::accept_waveform`].
+    pub window: f32,
+    /// Segments shorter than this many seconds are too short to embed and are skipped.
+    pub min_duration: f32,
+}
+
+impl Default for OnlineTrackerConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            alpha: 0.1,
+            max_speakers: None,
+            window: 1.5,
+            min_duration: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackerEvent {
+    /// A speaker was heard for the first time.
+    NewSpeaker(String),
+    /// The speaker differs from the one of the previous segment.
+    SpeakerChanged {
+        from: Option<String>,
+        to: String,
+        /// Start of the new speaker's segment in seconds.
+        at: f32,
+    },
+    /// A segment was labeled.
+    Turn(SpeakerTurn),
+}
+
+/// The centroid bookkeeping of [`OnlineTracker`], for embeddings computed elsewhere.
+#[derive(Debug, Clone)]
+pub struct SpeakerTracker {
+    config: OnlineTrackerConfig,
+    centroids: Vec<Vec<f32>>,
+    current: Option<usize>,
+}
+
+pub struct OnlineTracker {
+    extractor: Arc<EmbeddingExtractor>,
+    sample_rate: i32,
+    tracker: SpeakerTracker,
+    /// Audio waiting for a full window in [`OnlineTracker::accept_waveform`].
+    pending: Vec<f32>,
+    /// Samples received by [`OnlineTracker::accept_waveform`] before `pending`.
+    offset: usize,
+}
+
+impl OnlineTracker {
+    /// `extractor` may be an `Arc` also used by a verifier or [`super::diarize`].
+    pub fn new(
+        extractor: impl Into<Arc<EmbeddingExtractor>>,
+        sample_rate: i32,
+        config: OnlineTrackerConfig,
+    ) -> Self {
+        Self {
+            extractor: extractor.into(),
+            sample_rate,
+            tracker: SpeakerTracker::new(config),
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Label a speech segment, e.g. from a VAD.
+    pub fn accept_speech_segment(&mut self, segment: SpeechSegment) -> Result<Vec<TrackerEvent>> {
+        self.accept_segment(segment.start_sec(), segment.samples)
+    }
+
+    /// Label a segment of audio starting at `start` seconds.
+    pub fn accept_segment(&mut self, start: f32, samples: Vec<f32>) -> Result<Vec<TrackerEvent>> {
+        let duration = samples.len() as f32 / self.sample_rate as f32;
+        if duration < self.tracker.config.min_duration {
+            return Ok(Vec::new());
+        }
+        let embedding = self.extractor.embed(self.sample_rate, &samples)?;
+        Ok(self
+            .tracker
+            .accept_embedding(start, start + duration, &embedding))
+    }
+
+    /// Feed continuous audio, labeled one window at a time.
+    pub fn accept_waveform(&mut self, samples: &[f32]) -> Result<Vec<TrackerEvent>> {
+        let window = ((self.tracker.config.window * self.sample_rate as f32) as usize).max(1);
+        self.pending.extend_from_slice(samples);
+        let mut events = Vec::new();
+        while self.pending.len() >= window {
+            let rest = self.pending.split_off(window);
+            let chunk = std::mem::replace(&mut self.pending, rest);
+            let start = self.offset as f32 / self.sample_rate as f32;
+            self.offset += window;
+            events.extend(self.accept_segment(start, chunk)?);
+        }
+        Ok(events)
+    }
+
+    /// Label the audio left over by [`OnlineTracker::accept_waveform`].
+    pub fn flush(&mut self) -> Result<Vec<TrackerEvent>> {
+        let chunk = std::mem::take(&mut self.pending);
+        let start = self.offset as f32 / self.sample_rate as f32;
+        self.offset += chunk.len();
+        self.accept_segment(start, chunk)
+    }
+
+    pub fn tracker(&self) -> &SpeakerTracker {
+        &self.tracker
+    }
+
+    pub fn into_inner(self) -> Arc<EmbeddingExtractor> {
+        self.extractor
+    }
+}
+
+impl SpeakerTracker {
+    pub fn new(config: OnlineTrackerConfig) -> Self {
+        Self {
+            config,
+            centroids: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Label an already computed embedding of the audio from `start` to `end` seconds.
+    pub fn accept_embedding(
+        &mut self,
+        start: f32,
+        end: f32,
+        embedding: &[f32],
+    ) -> Vec<TrackerEvent> {
+        let mut events = Vec::new();
+        let embedding = normalize(embedding);
+        let best = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(i, centroid)| (i, cosine_similarity(centroid, &embedding)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let at_limit = self
+            .config
+            .max_speakers
+            .is_some_and(|max| self.centroids.len() >= max.max(1));
+
+        let speaker = match best {
+            Some((i, score)) if score >= self.config.threshold || at_limit => {
+                let alpha = self.config.alpha.clamp(0.0, 1.0);
+                let centroid = &mut self.centroids[i];
+                for (c, e) in centroid.iter_mut().zip(&embedding) {
+                    *c = (1.0 - alpha) * *c + alpha * e;
+                }
+                i
+            }
+            _ => {
+                self.centroids.push(embedding);
+                let speaker = self.centroids.len() - 1;
+                events.push(TrackerEvent::NewSpeaker(speaker_name(speaker)));
+                speaker
+            }
+        };
+
+        if self.current != Some(speaker) {
+            events.push(TrackerEvent::SpeakerChanged {
+                from: self.current.map(speaker_name),
+                to: speaker_name(speaker),
+                at: start,
+            });
+            self.current = Some(speaker);
+        }
+        events.push(TrackerEvent::Turn(SpeakerTurn {
+            speaker: speaker_name(speaker),
+            start,
+            end,
+        }));
+        events
+    }
+
+    /// Label of the last segment.
+    pub fn current_speaker(&self) -> Option<String> {
+        self.current.map(speaker_name)
+    }
+
+    pub fn num_speakers(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Running centroid of each speaker, in order of appearance.
+    pub fn centroids(&self) -> &[Vec<f32>] {
+        &self.centroids
+    }
+}
+
+fn speaker_name(index: usize) -> String {
+    format!("speaker_{}", index)
+}
+
+fn normalize(embedding: &[f32]) -> Vec<f32> {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        return embedding.to_vec();
+    }
+    embedding.iter().map(|x| x / norm).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_and_limit() {
+        let config = OnlineTrackerConfig {
+            threshold: 0.8,
+            max_speakers: Some(2),
+            ..Default::default()
+        };
+        let mut tracker = SpeakerTracker::new(config);
+        let embeddings = [
+            [1.0, 0.0, 0.0],
+            [0.9, 0.1, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.2, 1.0],
+            [1.0, 0.1, 0.0],
+        ];
+        let mut labels = Vec::new();
+        let mut changes = 0;
+        for (i, embedding) in embeddings.iter().enumerate() {
+            for event in tracker.accept_embedding(i as f32, i as f32 + 1.0, embedding) {
+                match event {
+                    TrackerEvent::Turn(turn) => labels.push(turn.speaker),
+                    TrackerEvent::SpeakerChanged { .. } => changes += 1,
+                    TrackerEvent::NewSpeaker(_) => {}
+                }
+            }
+        }
+        // The third direction can't get its own speaker and goes to the closest one
+        assert_eq!(
+            labels,
+            [
+                "speaker_0",
+                "speaker_0",
+                "speaker_1",
+                "speaker_1",
+                "speaker_0"
+            ]
+        );
+        assert_eq!(changes, 3);
+        assert_eq!(tracker.num_speakers(), 2);
+    }
+}
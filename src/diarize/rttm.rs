@@ -0,0 +1,103 @@
+//! Read and write speaker turns in the RTTM format used by diarization benchmarks.
+//!
+//! Only `SPEAKER` lines are used, e.g.
+//! `SPEAKER meeting 1 12.340 2.100 <NA> <NA> alice <NA> <NA>`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use eyre::{bail, Result};
+
+use super::SpeakerTurn;
+
+/// Write `turns` of the recording `file_id` as RTTM lines.
+pub fn write_rttm<W: Write>(writer: &mut W, file_id: &str, turns: &[SpeakerTurn]) -> Result<()> {
+    if file_id.is_empty() || file_id.contains(char::is_whitespace) {
+        bail!("Invalid RTTM file id {:?}", file_id)
+    }
+    for turn in turns {
+        if turn.speaker.is_empty() || turn.speaker.contains(char::is_whitespace) {
+            bail!("Invalid RTTM speaker name {:?}", turn.speaker)
+        }
+        writeln!(
+            writer,
+            "SPEAKER {} 1 {:.3} {:.3} <NA> <NA> {} <NA> <NA>",
+            file_id,
+            turn.start,
+            turn.end - turn.start,
+            turn.speaker
+        )?;
+    }
+    Ok(())
+}
+
+/// Read the speaker turns of every recording, keyed by file id.
+///
+/// Lines other than `SPEAKER` lines and `;;` comments are ignored.
+pub fn read_rttm<R: BufRead>(reader: R) -> Result<BTreeMap<String, Vec<SpeakerTurn>>> {
+    let mut recordings: BTreeMap<String, Vec<SpeakerTurn>> = BTreeMap::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() != Some(&"SPEAKER") {
+            continue;
+        }
+        if fields.len() < 8 {
+            bail!(
+                "RTTM line {} has {} fields, expected at least 8",
+                number + 1,
+                fields.len()
+            )
+        }
+        let (Ok(start), Ok(duration)) = (fields[3].parse::<f32>(), fields[4].parse::<f32>()) else {
+            bail!("RTTM line {} has an invalid start or duration", number + 1)
+        };
+        if duration < 0.0 {
+            bail!("RTTM line {} has a negative duration", number + 1)
+        }
+        recordings
+            .entry(fields[1].to_string())
+            .or_default()
+            .push(SpeakerTurn::new(fields[7], start, start + duration));
+    }
+    Ok(recordings)
+}
+
+pub fn save_rttm<P: AsRef<Path>>(path: P, file_id: &str, turns: &[SpeakerTurn]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_rttm(&mut writer, file_id, turns)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_rttm<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, Vec<SpeakerTurn>>> {
+    read_rttm(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_read() {
+        let turns = vec![
+            SpeakerTurn::new("alice", 0.5, 2.0),
+            SpeakerTurn::new("bob", 2.25, 3.0),
+        ];
+        let mut buffer = Vec::new();
+        write_rttm(&mut buffer, "meeting", &turns).unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.starts_with("SPEAKER meeting 1 0.500 1.500 <NA> <NA> alice <NA> <NA>\n"));
+
+        let text = format!(";; comment\n{}", text);
+        let recordings = read_rttm(text.as_bytes()).unwrap();
+        assert_eq!(recordings["meeting"], turns);
+
+        let negative = "SPEAKER meeting 1 1.000 -0.500 <NA> <NA> alice <NA> <NA>";
+        assert!(read_rttm(negative.as_bytes()).is_err());
+    }
+}
@@ -1,11 +1,33 @@
 use eyre::{bail, Result};
-use std::ffi::{CStr, CString};
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+    path::Path,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
-use crate::cstr_to_string;
+use crate::{cstr_to_string, speaker_id::ModelId};
 
+/// Magic bytes at the start of a file written by [`EmbeddingManager::save`].
+const FILE_MAGIC: &[u8; 8] = b"SHRPASPK";
+const FILE_VERSION: u32 = 1;
+
+/// Registry of known speakers.
+///
+/// Clones share the same speakers. Any number of threads may search at once,
+/// registering or removing a speaker waits for them to finish.
 #[derive(Debug, Clone)]
 pub struct EmbeddingManager {
-    pub(crate) manager: *const sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManager,
+    inner: Arc<Inner>,
+    dimension: i32,
+}
+
+#[derive(Debug)]
+struct Inner {
+    manager: *const sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManager,
+    /// Copy of the registered embeddings, the native manager doesn't expose them.
+    /// The lock also guards every call into the native manager.
+    embeddings: RwLock<BTreeMap<String, Vec<f32>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -18,40 +40,57 @@ impl EmbeddingManager {
     pub fn new(dimension: i32) -> Self {
         unsafe {
             let manager = sherpa_rs_sys::SherpaOnnxCreateSpeakerEmbeddingManager(dimension);
-            Self { manager }
+            let inner = Inner {
+                manager,
+                embeddings: RwLock::new(BTreeMap::new()),
+            };
+            Self {
+                inner: Arc::new(inner),
+                dimension,
+            }
         }
     }
 
-    pub fn search(&mut self, embedding: &[f32], threshold: f32) -> Option<String> {
+    pub fn dimension(&self) -> i32 {
+        self.dimension
+    }
+
+    pub fn search(&self, embedding: &[f32], threshold: f32) -> Result<Option<String>> {
+        self.check_dimension(embedding)?;
+        let _guard = self.read();
         unsafe {
             let name = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerSearch(
-                self.manager,
-                embedding.to_owned().as_mut_ptr(),
+                self.inner.manager,
+                embedding.as_ptr(),
                 threshold,
             );
             if name.is_null() {
-                return None;
+                return Ok(None);
             }
             let cstr = CStr::from_ptr(name);
-            Some(cstr.to_str().unwrap_or_default().to_string())
+            let name = cstr.to_str().unwrap_or_default().to_string();
+            sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerFreeSearch(cstr.as_ptr());
+            Ok(Some(name))
         }
     }
 
     pub fn get_best_matches(
-        &mut self,
+        &self,
         embedding: &[f32],
         threshold: f32,
         n: i32,
-    ) -> Vec<SpeakerMatch> {
+    ) -> Result<Vec<SpeakerMatch>> {
+        self.check_dimension(embedding)?;
+        let _guard = self.read();
         unsafe {
             let result_ptr = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerGetBestMatches(
-                self.manager,
-                embedding.to_owned().as_mut_ptr(),
+                self.inner.manager,
+                embedding.as_ptr(),
                 threshold,
                 n,
             );
             if result_ptr.is_null() {
-                return Vec::new();
+                return Ok(Vec::new());
             }
             let result = result_ptr.read();
 
@@ -64,34 +103,349 @@ impl EmbeddingManager {
                 matches.push(SpeakerMatch { name, score });
             }
             sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerFreeBestMatches(result_ptr);
-            matches
+            Ok(matches)
         }
     }
 
-    pub fn add(&mut self, name: String, embedding: &mut [f32]) -> Result<()> {
+    pub fn add(&self, name: String, embedding: &mut [f32]) -> Result<()> {
+        self.check_dimension(embedding)?;
         let name_cstr = CString::new(name.clone())?;
 
+        let mut embeddings = self.write();
         unsafe {
             let status = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerAdd(
-                self.manager,
-                name_cstr.into_raw(),
+                self.inner.manager,
+                name_cstr.as_ptr(),
                 embedding.as_mut_ptr(),
             );
-            if status.is_negative() {
+            if status == 0 {
+                bail!("Failed to register {}", name)
+            }
+        }
+        embeddings.insert(name, embedding.to_vec());
+        Ok(())
+    }
+
+    /// Register a speaker from several embeddings, e.g. one per enrollment utterance.
+    /// The speaker is represented by their average.
+    pub fn add_list<E: AsRef<[f32]>>(&self, name: &str, embeddings: &[E]) -> Result<()> {
+        if embeddings.is_empty() {
+            bail!("No embeddings to register {}", name);
+        }
+        let mut flattened = Vec::with_capacity(embeddings.len() * self.dimension as usize);
+        for embedding in embeddings {
+            self.check_dimension(embedding.as_ref())?;
+            flattened.extend_from_slice(embedding.as_ref());
+        }
+        let name_cstr = CString::new(name)?;
+
+        let mut registered = self.write();
+        unsafe {
+            let status = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerAddListFlattened(
+                self.inner.manager,
+                name_cstr.as_ptr(),
+                flattened.as_ptr(),
+                embeddings.len() as i32,
+            );
+            if status == 0 {
                 bail!("Failed to register {}", name)
             }
-            Ok(())
+        }
+        let mut mean = vec![0.0; self.dimension as usize];
+        for embedding in embeddings {
+            for (sum, value) in mean.iter_mut().zip(embedding.as_ref()) {
+                *sum += value / embeddings.len() as f32;
+            }
+        }
+        registered.insert(name.to_string(), mean);
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        let name_cstr = CString::new(name)?;
+        let mut embeddings = self.write();
+        unsafe {
+            let status = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerRemove(
+                self.inner.manager,
+                name_cstr.as_ptr(),
+            );
+            if status == 0 {
+                bail!("Speaker {} is not registered", name)
+            }
+        }
+        embeddings.remove(name);
+        Ok(())
+    }
+
+    /// Whether `embedding` belongs to the registered speaker `name`.
+    pub fn verify(&self, name: &str, embedding: &[f32], threshold: f32) -> Result<bool> {
+        self.check_dimension(embedding)?;
+        let embeddings = self.read();
+        if !embeddings.contains_key(name) {
+            bail!("Speaker {} is not registered", name)
+        }
+        let name_cstr = CString::new(name)?;
+        unsafe {
+            let status = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerVerify(
+                self.inner.manager,
+                name_cstr.as_ptr(),
+                embedding.as_ptr(),
+                threshold,
+            );
+            Ok(status == 1)
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        let Ok(name_cstr) = CString::new(name) else {
+            return false;
+        };
+        let _guard = self.read();
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerContains(
+                self.inner.manager,
+                name_cstr.as_ptr(),
+            ) == 1
+        }
+    }
+
+    pub fn num_speakers(&self) -> usize {
+        let _guard = self.read();
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerNumSpeakers(self.inner.manager) as usize
+        }
+    }
+
+    /// Names of all registered speakers.
+    pub fn speakers(&self) -> Vec<String> {
+        let _guard = self.read();
+        unsafe {
+            let names_ptr =
+                sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerGetAllSpeakers(self.inner.manager);
+            if names_ptr.is_null() {
+                return Vec::new();
+            }
+            let mut names = Vec::new();
+            // The list is terminated by a null pointer
+            let mut i = 0;
+            while !(*names_ptr.add(i)).is_null() {
+                names.push(cstr_to_string!(*names_ptr.add(i)));
+                i += 1;
+            }
+            sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingManagerFreeAllSpeakers(names_ptr);
+            names
         }
     }
+
+    /// Write all registered speakers to `path`, tagged with the model that
+    /// computed their embeddings.
+    pub fn save<P: AsRef<Path>>(&self, path: P, model: &ModelId) -> Result<()> {
+        let bytes = encode(self.dimension, model, &self.read());
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Create a manager with the speakers saved at `path`.
+    ///
+    /// Fails if they were computed with a model other than `model` or have a
+    /// dimension other than `dimension`.
+    pub fn load<P: AsRef<Path>>(path: P, model: &ModelId, dimension: i32) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let (saved_dimension, saved_model, embeddings) = decode(&bytes)?;
+        if saved_dimension != dimension {
+            bail!(
+                "{} has embeddings of dimension {}, expected {}",
+                path.display(),
+                saved_dimension,
+                dimension
+            )
+        }
+        if !saved_model.same_model(model) {
+            bail!(
+                "{} was created with model {} ({} bytes), not {} ({} bytes)",
+                path.display(),
+                saved_model.name,
+                saved_model.size,
+                model.name,
+                model.size
+            )
+        }
+        let manager = Self::new(dimension);
+        for (name, mut embedding) in embeddings {
+            manager.add(name, &mut embedding)?;
+        }
+        Ok(manager)
+    }
+
+    /// A panic while holding the lock can't leave the speakers half updated, so
+    /// poisoning is ignored.
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<String, Vec<f32>>> {
+        self.inner
+            .embeddings
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, Vec<f32>>> {
+        self.inner
+            .embeddings
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn check_dimension(&self, embedding: &[f32]) -> Result<()> {
+        if embedding.len() != self.dimension as usize {
+            bail!(
+                "Embedding has dimension {}, expected {}",
+                embedding.len(),
+                self.dimension
+            )
+        }
+        Ok(())
+    }
+}
+
+fn encode(dimension: i32, model: &ModelId, embeddings: &BTreeMap<String, Vec<f32>>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let put_str = |bytes: &mut Vec<u8>, s: &str| {
+        bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(s.as_bytes());
+    };
+    bytes.extend_from_slice(FILE_MAGIC);
+    bytes.extend_from_slice(&FILE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(dimension as u32).to_le_bytes());
+    put_str(&mut bytes, &model.name);
+    bytes.extend_from_slice(&model.size.to_le_bytes());
+    bytes.extend_from_slice(&model.hash.to_le_bytes());
+    bytes.extend_from_slice(&(embeddings.len() as u32).to_le_bytes());
+    for (name, embedding) in embeddings {
+        put_str(&mut bytes, name);
+        for value in embedding {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+type Decoded = (i32, ModelId, Vec<(String, Vec<f32>)>);
+
+fn decode(bytes: &[u8]) -> Result<Decoded> {
+    let mut reader = Reader { bytes };
+    if reader.take(FILE_MAGIC.len())? != FILE_MAGIC {
+        bail!("Not a speaker embeddings file")
+    }
+    let version = reader.u32()?;
+    if version != FILE_VERSION {
+        bail!("Unsupported speaker embeddings file version {}", version)
+    }
+    let dimension = reader.u32()? as usize;
+    let model = ModelId {
+        name: reader.string()?,
+        size: reader.u64()?,
+        hash: reader.u64()?,
+    };
+    let count = reader.u32()?;
+    let mut embeddings = Vec::new();
+    for _ in 0..count {
+        let name = reader.string()?;
+        let embedding = reader
+            .take(dimension * 4)?
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        embeddings.push((name, embedding));
+    }
+    Ok((dimension as i32, model, embeddings))
 }
 
-unsafe impl Send for EmbeddingManager {}
-unsafe impl Sync for EmbeddingManager {}
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            bail!("Speaker embeddings file is truncated")
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+}
+
+// The native manager is only used through the lock in `Inner::embeddings`
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
 
-impl Drop for EmbeddingManager {
+impl Drop for Inner {
     fn drop(&mut self) {
         unsafe {
             sherpa_rs_sys::SherpaOnnxDestroySpeakerEmbeddingManager(self.manager);
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let model = ModelId {
+            name: "model.onnx".into(),
+            size: 42,
+            hash: 7,
+        };
+        let mut embeddings = BTreeMap::new();
+        embeddings.insert("alice".to_string(), vec![0.5, -1.0, 2.0]);
+        embeddings.insert("bob".to_string(), vec![1.0, 0.0, 0.25]);
+
+        let bytes = encode(3, &model, &embeddings);
+        let (dimension, decoded_model, decoded) = decode(&bytes).unwrap();
+        assert_eq!(dimension, 3);
+        assert_eq!(decoded_model, model);
+        // A renamed or moved copy of the model is still the same model
+        let renamed = ModelId {
+            name: "copy.onnx".into(),
+            ..model
+        };
+        assert!(decoded_model.same_model(&renamed));
+        assert_eq!(decoded, embeddings.into_iter().collect::<Vec<_>>());
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_clone_and_drop() {
+        let manager = EmbeddingManager::new(4);
+        let clones: Vec<EmbeddingManager> = (0..8).map(|_| manager.clone()).collect();
+        drop(manager);
+        std::thread::scope(|scope| {
+            for clone in clones {
+                scope.spawn(move || assert_eq!(clone.dimension(), 4));
+            }
+        });
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let manager = EmbeddingManager::new(4);
+        let embedding = [0.5; 3];
+        assert!(manager.search(&embedding, 0.5).is_err());
+        assert!(manager.get_best_matches(&embedding, 0.5, 2).is_err());
+        assert!(manager.add("alice".into(), &mut [0.5; 3]).is_err());
+    }
+}
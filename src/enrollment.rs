@@ -0,0 +1,275 @@
+//! Check enrollment audio before a speaker is registered.
+//!
+//! A speaker enrolled from short, clipped, quiet, noisy or multi-speaker audio
+//! matches badly later on, so [`check_enrollment`] measures the recording and
+//! lists every problem it finds.
+
+use std::fmt;
+
+use eyre::{bail, Result};
+
+use crate::{
+    embedding_manager::EmbeddingManager,
+    speaker_id::{cosine_similarity, EmbeddingExtractor},
+    vad::VoiceActivityDetector,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnrollmentConfig {
+    /// Seconds of speech needed, without pauses.
+    pub min_speech: f32,
+    /// Samples at or above this magnitude count as clipped.
+    pub clip_level: f32,
+    /// Largest allowed fraction of clipped samples.
+    pub max_clipped_ratio: f32,
+    /// Lowest allowed speech level in dBFS.
+    pub min_level: f32,
+    /// Lowest allowed difference between the speech and the non-speech level in dB.
+    pub min_snr: f32,
+    /// Seconds of speech per embedding when checking consistency.
+    pub sub_segment: f32,
+    /// Lowest allowed cosine similarity of a sub-segment to the average embedding.
+    pub min_consistency: f32,
+    /// Threads computing the sub-segment embeddings.
+    pub num_workers: usize,
+}
+
+impl Default for EnrollmentConfig {
+    fn default() -> Self {
+        Self {
+            min_speech: 5.0,
+            clip_level: 0.99,
+            max_clipped_ratio: 0.001,
+            min_level: -35.0,
+            min_snr: 15.0,
+            sub_segment: 2.0,
+            min_consistency: 0.6,
+            num_workers: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    TooShort {
+        speech: f32,
+        required: f32,
+    },
+    Clipped {
+        ratio: f32,
+    },
+    TooQuiet {
+        level: f32,
+    },
+    Noisy {
+        snr: f32,
+    },
+    /// The sub-segments don't sound like one speaker.
+    Inconsistent {
+        consistency: f32,
+    },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::TooShort { speech, required } => write!(
+                f,
+                "only {:.1}s of speech, at least {:.1}s needed",
+                speech, required
+            ),
+            Rejection::Clipped { ratio } => {
+                write!(f, "{:.2}% of the samples are clipped", ratio * 100.0)
+            }
+            Rejection::TooQuiet { level } => write!(f, "speech is too quiet ({:.1} dBFS)", level),
+            Rejection::Noisy { snr } => write!(f, "background is too loud (SNR {:.1} dB)", snr),
+            Rejection::Inconsistent { consistency } => write!(
+                f,
+                "the recording seems to contain several voices (consistency {:.2})",
+                consistency
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnrollmentReport {
+    /// Seconds of speech found by the VAD.
+    pub speech_duration: f32,
+    pub clipped_ratio: f32,
+    /// Speech level in dBFS.
+    pub level: f32,
+    /// Speech level over the non-speech level in dB, `None` without pauses.
+    pub snr: Option<f32>,
+    /// Lowest similarity of a sub-segment to the average, `None` with a single sub-segment.
+    pub consistency: Option<f32>,
+    /// Embedding of each sub-segment.
+    pub embeddings: Vec<Vec<f32>>,
+    pub rejections: Vec<Rejection>,
+}
+
+impl EnrollmentReport {
+    pub fn is_accepted(&self) -> bool {
+        self.rejections.is_empty()
+    }
+
+    /// Register the speaker from the sub-segment embeddings if the enrollment was accepted.
+    pub fn register(&self, manager: &EmbeddingManager, name: &str) -> Result<()> {
+        if !self.is_accepted() {
+            let reasons: Vec<String> = self.rejections.iter().map(|r| r.to_string()).collect();
+            bail!("Enrollment of {} rejected: {}", name, reasons.join("; "))
+        }
+        manager.add_list(name, &self.embeddings)
+    }
+}
+
+/// Measure an enrollment recording and decide whether it is good enough.
+pub fn check_enrollment<V: VoiceActivityDetector>(
+    vad: &mut V,
+    extractor: &EmbeddingExtractor,
+    samples: &[f32],
+    config: &EnrollmentConfig,
+) -> Result<EnrollmentReport> {
+    let sample_rate = vad.sample_rate();
+    let segments = vad.segment_buffer(samples);
+    let ranges: Vec<(usize, usize)> = segments.iter().map(|s| (s.start, s.end)).collect();
+    let stats = SignalStats::new(samples, &ranges, config.clip_level);
+
+    let speech: Vec<f32> = segments
+        .iter()
+        .flat_map(|s| s.samples.iter().copied())
+        .collect();
+    let speech_duration = speech.len() as f32 / sample_rate as f32;
+    // Too little speech may not even be enough for one embedding
+    let embeddings = if speech_duration < config.min_speech {
+        Vec::new()
+    } else {
+        let sub_len = ((config.sub_segment * sample_rate as f32) as usize).max(1);
+        let mut sub_segments: Vec<&[f32]> = speech.chunks(sub_len).collect();
+        // A short tail embeds poorly, join it with the previous piece
+        if sub_segments.len() > 1 && sub_segments.last().unwrap().len() < sub_len / 2 {
+            sub_segments.pop();
+            let start = (sub_segments.len() - 1) * sub_len;
+            *sub_segments.last_mut().unwrap() = &speech[start..];
+        }
+        extractor
+            .embed_batch(sample_rate, &sub_segments, config.num_workers)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?
+    };
+    let consistency = consistency(&embeddings);
+
+    let mut rejections = Vec::new();
+    if speech_duration < config.min_speech {
+        rejections.push(Rejection::TooShort {
+            speech: speech_duration,
+            required: config.min_speech,
+        });
+    }
+    if stats.clipped_ratio > config.max_clipped_ratio {
+        rejections.push(Rejection::Clipped {
+            ratio: stats.clipped_ratio,
+        });
+    }
+    if stats.speech_level < config.min_level {
+        rejections.push(Rejection::TooQuiet {
+            level: stats.speech_level,
+        });
+    }
+    let snr = stats.noise_level.map(|noise| stats.speech_level - noise);
+    if let Some(snr) = snr.filter(|&snr| snr < config.min_snr) {
+        rejections.push(Rejection::Noisy { snr });
+    }
+    if let Some(consistency) = consistency.filter(|&c| c < config.min_consistency) {
+        rejections.push(Rejection::Inconsistent { consistency });
+    }
+
+    Ok(EnrollmentReport {
+        speech_duration,
+        clipped_ratio: stats.clipped_ratio,
+        level: stats.speech_level,
+        snr,
+        consistency,
+        embeddings,
+        rejections,
+    })
+}
+
+struct SignalStats {
+    clipped_ratio: f32,
+    /// RMS level of the speech ranges in dBFS.
+    speech_level: f32,
+    /// RMS level outside the speech ranges in dBFS, `None` if there is no such audio.
+    noise_level: Option<f32>,
+}
+
+impl SignalStats {
+    fn new(samples: &[f32], speech: &[(usize, usize)], clip_level: f32) -> Self {
+        let clipped = samples.iter().filter(|s| s.abs() >= clip_level).count();
+        let mut speech_energy = (0.0f64, 0usize);
+        let mut noise_energy = (0.0f64, 0usize);
+        let mut ranges = speech.iter().peekable();
+        for (i, &sample) in samples.iter().enumerate() {
+            while ranges.peek().is_some_and(|&&(_, end)| end <= i) {
+                ranges.next();
+            }
+            let energy = if ranges.peek().is_some_and(|&&(start, _)| start <= i) {
+                &mut speech_energy
+            } else {
+                &mut noise_energy
+            };
+            energy.0 += (sample as f64).powi(2);
+            energy.1 += 1;
+        }
+        let level = |(sum, count): (f64, usize)| {
+            (count > 0).then(|| (10.0 * (sum / count as f64).max(1e-10).log10()) as f32)
+        };
+        Self {
+            clipped_ratio: clipped as f32 / samples.len().max(1) as f32,
+            speech_level: level(speech_energy).unwrap_or(-100.0),
+            noise_level: level(noise_energy),
+        }
+    }
+}
+
+/// Lowest similarity of an embedding to the average of all of them.
+fn consistency(embeddings: &[Vec<f32>]) -> Option<f32> {
+    if embeddings.len() < 2 {
+        return None;
+    }
+    let mut mean = vec![0.0; embeddings[0].len()];
+    for embedding in embeddings {
+        for (m, e) in mean.iter_mut().zip(embedding) {
+            *m += e / embeddings.len() as f32;
+        }
+    }
+    embeddings
+        .iter()
+        .map(|embedding| cosine_similarity(embedding, &mean))
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_stats() {
+        // Noise at 0.01, speech at 0.1 with two clipped samples
+        let mut samples = vec![0.01; 100];
+        for sample in &mut samples[20..60] {
+            *sample = 0.1;
+        }
+        samples[30] = 1.0;
+        samples[31] = -1.0;
+
+        let stats = SignalStats::new(&samples, &[(20, 60)], 0.99);
+        assert_eq!(stats.clipped_ratio, 0.02);
+        assert!((stats.noise_level.unwrap() + 40.0).abs() < 0.01);
+        assert!(stats.speech_level > -20.0);
+
+        let consistent = consistency(&[vec![1.0, 0.0], vec![1.0, 0.1]]).unwrap();
+        let mixed = consistency(&[vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert!(consistent > 0.99 && mixed < 0.8);
+    }
+}
@@ -1,10 +1,15 @@
 pub mod add_punctuation;
+pub mod diarize;
 pub mod embedding_manager;
+pub mod enrollment;
 pub mod language_id;
+pub mod npy;
 pub mod online;
 pub mod speaker_id;
+pub mod text2token;
 pub mod transcribe;
 pub mod vad;
+pub mod verification;
 
 use eyre::{bail, Result};
 
@@ -73,3 +78,27 @@ macro_rules! cstr_to_string {
         std::ffi::CStr::from_ptr($ptr).to_string_lossy().to_string()
     };
 }
+
+/// Copy the `count` tokens and token start times of a recognizer result.
+///
+/// Models that don't report times leave `timestamps` null, which gives no timestamps.
+pub(crate) unsafe fn read_tokens(
+    tokens: *const *const std::ffi::c_char,
+    timestamps: *const f32,
+    count: i32,
+) -> (Vec<String>, Vec<f32>) {
+    let count = count.max(0) as usize;
+    if tokens.is_null() || count == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let tokens = std::slice::from_raw_parts(tokens, count)
+        .iter()
+        .map(|&token| cstr_to_string!(token))
+        .collect();
+    let timestamps = if timestamps.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(timestamps, count).to_vec()
+    };
+    (tokens, timestamps)
+}
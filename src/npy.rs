@@ -0,0 +1,694 @@
+//! Exchange speaker embeddings with NumPy through `.npy` and `.npz` files.
+//!
+//! An [`EmbeddingSet`] is stored in an `.npz` archive as `embeddings.npy`
+//! (float32, `(n, dim)`), `labels.npy` (unicode strings, `(n,)`) and optionally
+//! `times.npy` (float32 start and end seconds, `(n, 2)`):
+//!
+//! ```python
+//! data = np.load("embeddings.npz")
+//! data["embeddings"], data["labels"], data["times"]
+//! ```
+//!
+//! Archives must be written with `np.savez`, compressed archives are not supported.
+
+use std::{collections::BTreeMap, path::Path};
+
+use eyre::{bail, Result};
+
+use crate::{
+    diarize::SpeakerTurn, embedding_manager::EmbeddingManager, speaker_id::EmbeddingExtractor,
+};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Contents of a `.npy` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NpyData {
+    /// `float32` or `float64` values, converted to `f32`.
+    Float(Vec<f32>),
+    /// Fixed-width unicode strings with trailing NULs removed.
+    Str(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NpyArray {
+    pub shape: Vec<usize>,
+    pub data: NpyData,
+}
+
+impl NpyArray {
+    pub fn float(shape: Vec<usize>, data: Vec<f32>) -> Self {
+        Self {
+            shape,
+            data: NpyData::Float(data),
+        }
+    }
+
+    pub fn strings(data: Vec<String>) -> Self {
+        Self {
+            shape: vec![data.len()],
+            data: NpyData::Str(data),
+        }
+    }
+
+    /// Encode as a version 1.0 `.npy` file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let expected: usize = self.shape.iter().product();
+        let (descr, body) = match &self.data {
+            NpyData::Float(values) => {
+                if values.len() != expected {
+                    bail!("{} values don't fit shape {:?}", values.len(), self.shape)
+                }
+                let body: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+                ("<f4".to_string(), body)
+            }
+            NpyData::Str(values) => {
+                if values.len() != expected {
+                    bail!("{} strings don't fit shape {:?}", values.len(), self.shape)
+                }
+                let width = values
+                    .iter()
+                    .map(|v| v.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                let mut body = Vec::with_capacity(values.len() * width * 4);
+                for value in values {
+                    let chars: Vec<char> = value.chars().collect();
+                    for i in 0..width {
+                        let c = chars.get(i).map_or(0, |&c| c as u32);
+                        body.extend_from_slice(&c.to_le_bytes());
+                    }
+                }
+                (format!("<U{}", width), body)
+            }
+        };
+
+        let shape = match self.shape.as_slice() {
+            [n] => format!("({},)", n),
+            dims => format!(
+                "({})",
+                dims.iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            descr, shape
+        );
+        // Magic, version and header length take 10 bytes, the data starts 64-byte aligned
+        let padding = 63 - (10 + header.len()) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut bytes = Vec::with_capacity(10 + header.len() + body.len());
+        bytes.extend_from_slice(NPY_MAGIC);
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&body);
+        Ok(bytes)
+    }
+
+    /// Decode a `.npy` file of `float32`, `float64` or unicode strings.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+            bail!("Not a .npy file")
+        }
+        let (header_len, header_start): (usize, usize) = match bytes[6] {
+            1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+            2 | 3 if bytes.len() >= 12 => (
+                u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize,
+                12,
+            ),
+            version => bail!("Unsupported .npy version {}", version),
+        };
+        let Some(header_end) = header_start.checked_add(header_len) else {
+            bail!(".npy header is too large")
+        };
+        let Some(header) = bytes.get(header_start..header_end) else {
+            bail!(".npy header is truncated")
+        };
+        let header = String::from_utf8_lossy(header);
+        let body = &bytes[header_end..];
+
+        let descr = header_value(&header, "descr")?
+            .trim_matches(|c| c == '\'' || c == '"')
+            .to_string();
+        if header_value(&header, "fortran_order")? != "False" {
+            bail!("Fortran-ordered arrays are not supported")
+        }
+        let shape: Vec<usize> = header_value(&header, "shape")?
+            .trim_matches(|c| c == '(' || c == ')')
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| d.parse())
+            .collect::<Result<_, _>>()?;
+        let Some(count) = shape
+            .iter()
+            .try_fold(1usize, |count, &d| count.checked_mul(d))
+        else {
+            bail!(".npy shape {:?} is too large", shape)
+        };
+
+        let data = match descr.as_str() {
+            "<f4" => NpyData::Float(
+                take(body, count, 4)?
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ),
+            "<f8" => NpyData::Float(
+                take(body, count, 8)?
+                    .chunks_exact(8)
+                    .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+                    .collect(),
+            ),
+            descr if descr.starts_with("<U") => {
+                let width: usize = descr[2..].parse()?;
+                let Some(item_size) = width.checked_mul(4) else {
+                    bail!(".npy dtype {} is too large", descr)
+                };
+                let strings = take(body, count, item_size)?
+                    .chunks_exact(item_size.max(1))
+                    .map(|item| {
+                        item.chunks_exact(4)
+                            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                            .take_while(|&c| c != 0)
+                            .filter_map(char::from_u32)
+                            .collect()
+                    })
+                    .take(count)
+                    .collect();
+                NpyData::Str(strings)
+            }
+            descr => bail!("Unsupported .npy dtype {}", descr),
+        };
+        Ok(Self { shape, data })
+    }
+}
+
+/// Embeddings with their speaker labels and, optionally, segment times.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EmbeddingSet {
+    pub labels: Vec<String>,
+    pub embeddings: Vec<Vec<f32>>,
+    /// Start and end of each embedded segment in seconds.
+    pub times: Option<Vec<(f32, f32)>>,
+}
+
+impl EmbeddingSet {
+    /// Embed each turn of `samples`, labeled with its speaker.
+    pub fn extract(
+        extractor: &EmbeddingExtractor,
+        sample_rate: i32,
+        samples: &[f32],
+        turns: &[SpeakerTurn],
+        num_workers: usize,
+    ) -> Result<Self> {
+        let to_sample =
+            |seconds: f32| ((seconds.max(0.0) * sample_rate as f32) as usize).min(samples.len());
+        let audio: Vec<&[f32]> = turns
+            .iter()
+            .map(|turn| {
+                let start = to_sample(turn.start);
+                &samples[start..to_sample(turn.end).max(start)]
+            })
+            .collect();
+        let embeddings = extractor
+            .embed_batch(sample_rate, &audio, num_workers)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            labels: turns.iter().map(|turn| turn.speaker.clone()).collect(),
+            embeddings,
+            times: Some(turns.iter().map(|turn| (turn.start, turn.end)).collect()),
+        })
+    }
+
+    pub fn push(&mut self, label: String, embedding: Vec<f32>, time: Option<(f32, f32)>) {
+        self.labels.push(label);
+        self.embeddings.push(embedding);
+        if let (Some(times), Some(time)) = (self.times.as_mut(), time) {
+            times.push(time);
+        } else if self.embeddings.len() == 1 {
+            self.times = time.map(|time| vec![time]);
+        } else {
+            // Times are only kept if every embedding has one
+            self.times = None;
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.embeddings.first().map_or(0, |e| e.len())
+    }
+
+    /// Write the embeddings alone as a `(n, dim)` `.npy` file.
+    pub fn save_npy<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.embedding_array()?.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn save_npz<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_npz()?)?;
+        Ok(())
+    }
+
+    pub fn load_npz<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_npz(&std::fs::read(path)?)
+    }
+
+    pub fn to_npz(&self) -> Result<Vec<u8>> {
+        if self.labels.len() != self.embeddings.len() {
+            bail!(
+                "{} labels for {} embeddings",
+                self.labels.len(),
+                self.embeddings.len()
+            )
+        }
+        let mut entries = vec![
+            ("embeddings.npy", self.embedding_array()?.to_bytes()?),
+            (
+                "labels.npy",
+                NpyArray::strings(self.labels.clone()).to_bytes()?,
+            ),
+        ];
+        if let Some(times) = &self.times {
+            let values = times
+                .iter()
+                .flat_map(|&(start, end)| [start, end])
+                .collect();
+            let array = NpyArray::float(vec![times.len(), 2], values);
+            entries.push(("times.npy", array.to_bytes()?));
+        }
+        zip::write(&entries)
+    }
+
+    pub fn from_npz(bytes: &[u8]) -> Result<Self> {
+        let mut entries = zip::read(bytes)?;
+        let mut array = |name: &str| -> Result<Option<NpyArray>> {
+            entries
+                .remove(name)
+                .map(|bytes| NpyArray::from_bytes(&bytes))
+                .transpose()
+        };
+        let Some(embeddings) = array("embeddings.npy")? else {
+            bail!("Archive has no embeddings")
+        };
+        let labels = array("labels.npy")?;
+        let times = array("times.npy")?;
+
+        let (NpyData::Float(values), [n, dim]) = (embeddings.data, embeddings.shape.as_slice())
+        else {
+            bail!("embeddings must be a 2-dimensional float array")
+        };
+        let embeddings: Vec<Vec<f32>> = values.chunks((*dim).max(1)).map(|e| e.to_vec()).collect();
+        if embeddings.len() != *n {
+            bail!("embeddings of dimension {} are not supported", dim)
+        }
+        let labels = match labels {
+            Some(NpyArray {
+                data: NpyData::Str(labels),
+                ..
+            }) => labels,
+            Some(_) => bail!("labels must be a string array"),
+            None => (0..*n).map(|i| i.to_string()).collect(),
+        };
+        let times = match times {
+            Some(NpyArray {
+                data: NpyData::Float(values),
+                shape,
+            }) if shape == [*n, 2] => Some(values.chunks(2).map(|t| (t[0], t[1])).collect()),
+            Some(_) => bail!("times must be a float array of shape ({}, 2)", n),
+            None => None,
+        };
+        if labels.len() != *n {
+            bail!("{} labels for {} embeddings", labels.len(), n)
+        }
+        Ok(Self {
+            labels,
+            embeddings,
+            times,
+        })
+    }
+
+    /// Create a manager with one speaker per label. A label with several
+    /// embeddings is registered from all of them.
+    pub fn to_manager(&self) -> Result<EmbeddingManager> {
+        let manager = EmbeddingManager::new(self.dimension() as i32);
+        self.import_into(&manager)?;
+        Ok(manager)
+    }
+
+    /// Register the speakers in an existing manager.
+    pub fn import_into(&self, manager: &EmbeddingManager) -> Result<()> {
+        let mut speakers: BTreeMap<&str, Vec<&[f32]>> = BTreeMap::new();
+        for (label, embedding) in self.labels.iter().zip(&self.embeddings) {
+            speakers.entry(label).or_default().push(embedding);
+        }
+        for (label, embeddings) in speakers {
+            manager.add_list(label, &embeddings)?;
+        }
+        Ok(())
+    }
+
+    fn embedding_array(&self) -> Result<NpyArray> {
+        let dimension = self.dimension();
+        if self.embeddings.iter().any(|e| e.len() != dimension) {
+            bail!("Embeddings have different dimensions")
+        }
+        Ok(NpyArray::float(
+            vec![self.embeddings.len(), dimension],
+            self.embeddings.concat(),
+        ))
+    }
+}
+
+/// Value of `key` in a `.npy` header like `{'descr': '<f4', 'shape': (2, 3), }`.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str> {
+    let pattern = format!("'{}':", key);
+    let Some(start) = header.find(&pattern) else {
+        bail!(".npy header has no {}", key)
+    };
+    let rest = header[start + pattern.len()..].trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')').map(|i| i + 1)
+    } else {
+        rest.find([',', '}'])
+    };
+    Ok(rest[..end.unwrap_or(rest.len())].trim())
+}
+
+/// The first `count` items of `item_size` bytes.
+fn take(bytes: &[u8], count: usize, item_size: usize) -> Result<&[u8]> {
+    match count
+        .checked_mul(item_size)
+        .and_then(|len| bytes.get(..len))
+    {
+        Some(bytes) => Ok(bytes),
+        None => bail!(".npy data is truncated"),
+    }
+}
+
+/// Just enough of the zip format for uncompressed `.npz` archives.
+mod zip {
+    use std::collections::HashMap;
+
+    use eyre::{bail, Result};
+
+    const LOCAL_HEADER: u32 = 0x04034b50;
+    const CENTRAL_HEADER: u32 = 0x02014b50;
+    const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+    pub fn write(entries: &[(&str, Vec<u8>)]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut central = Vec::new();
+        for (name, data) in entries {
+            if bytes.len() > u32::MAX as usize || data.len() > u32::MAX as usize {
+                bail!("Archive is too large")
+            }
+            let offset = bytes.len() as u32;
+            let crc = crc32(data);
+            let mut common = Vec::new();
+            put16(&mut common, 20); // version needed
+            put16(&mut common, 0); // flags
+            put16(&mut common, 0); // stored
+            put16(&mut common, 0); // time
+            put16(&mut common, 0x21); // date, 1980-01-01
+            put32(&mut common, crc);
+            put32(&mut common, data.len() as u32);
+            put32(&mut common, data.len() as u32);
+            put16(&mut common, name.len() as u16);
+            put16(&mut common, 0); // extra length
+
+            put32(&mut bytes, LOCAL_HEADER);
+            bytes.extend_from_slice(&common);
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(data);
+
+            put32(&mut central, CENTRAL_HEADER);
+            put16(&mut central, 20); // version made by
+            central.extend_from_slice(&common);
+            put16(&mut central, 0); // comment length
+            put16(&mut central, 0); // disk
+            put16(&mut central, 0); // internal attributes
+            put32(&mut central, 0); // external attributes
+            put32(&mut central, offset);
+            central.extend_from_slice(name.as_bytes());
+        }
+        let central_offset = bytes.len() as u32;
+        bytes.extend_from_slice(&central);
+        put32(&mut bytes, END_OF_CENTRAL_DIRECTORY);
+        put16(&mut bytes, 0);
+        put16(&mut bytes, 0);
+        put16(&mut bytes, entries.len() as u16);
+        put16(&mut bytes, entries.len() as u16);
+        put32(&mut bytes, central.len() as u32);
+        put32(&mut bytes, central_offset);
+        put16(&mut bytes, 0);
+        Ok(bytes)
+    }
+
+    /// Read every entry of an archive through its central directory.
+    pub fn read(bytes: &[u8]) -> Result<HashMap<String, Vec<u8>>> {
+        let Some(end) = (0..bytes.len().saturating_sub(21))
+            .rev()
+            .find(|&i| get32(bytes, i) == Some(END_OF_CENTRAL_DIRECTORY))
+        else {
+            bail!("Not a .npz archive")
+        };
+        let count = field16(bytes, end + 10)? as usize;
+        let mut position = field32(bytes, end + 16)? as usize;
+
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            if get32(bytes, position) != Some(CENTRAL_HEADER) {
+                bail!("Corrupt .npz central directory")
+            }
+            let method = field16(bytes, position + 10)?;
+            let crc = field32(bytes, position + 16)?;
+            let compressed = field32(bytes, position + 20)?;
+            let uncompressed = field32(bytes, position + 24)?;
+            let name_len = field16(bytes, position + 28)? as usize;
+            let extra_len = field16(bytes, position + 30)? as usize;
+            let comment_len = field16(bytes, position + 32)? as usize;
+            let offset = field32(bytes, position + 42)?;
+            let name_start = position + 46;
+            let name = String::from_utf8_lossy(slice(bytes, name_start, name_len)?).to_string();
+            let extra = slice(bytes, name_start + name_len, extra_len)?;
+            position = name_start + name_len + extra_len + comment_len;
+
+            // Values that don't fit 32 bits are in the zip64 extra field, in the
+            // order uncompressed size, compressed size, offset
+            let mut zip64 = zip64_fields(extra).into_iter();
+            let mut widen = |value: u32| match value {
+                u32::MAX => zip64.next().unwrap_or(u32::MAX as u64),
+                value => value as u64,
+            };
+            let uncompressed = widen(uncompressed);
+            let compressed = widen(compressed);
+            let Ok(offset) = usize::try_from(widen(offset)) else {
+                bail!("Corrupt .npz entry {}", name)
+            };
+
+            if method != 0 {
+                bail!("{} is compressed, write the archive with np.savez", name)
+            }
+            if compressed != uncompressed {
+                bail!("Corrupt .npz entry {}", name)
+            }
+            if get32(bytes, offset) != Some(LOCAL_HEADER) {
+                bail!("Corrupt .npz entry {}", name)
+            }
+            // The local header was found, so this stays within 128 KiB past the archive
+            let data_start = offset
+                + 30
+                + field16(bytes, offset + 26)? as usize
+                + field16(bytes, offset + 28)? as usize;
+            let Ok(len) = usize::try_from(compressed) else {
+                bail!(".npz archive is truncated")
+            };
+            let data = slice(bytes, data_start, len)?;
+            if crc32(data) != crc {
+                bail!("Checksum mismatch in {}", name)
+            }
+            entries.insert(name, data.to_vec());
+        }
+        Ok(entries)
+    }
+
+    fn zip64_fields(mut extra: &[u8]) -> Vec<u64> {
+        while extra.len() >= 4 {
+            let id = u16::from_le_bytes([extra[0], extra[1]]);
+            let len = (u16::from_le_bytes([extra[2], extra[3]]) as usize).min(extra.len() - 4);
+            if id == 1 {
+                return extra[4..4 + len]
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+                    .collect();
+            }
+            extra = &extra[4 + len..];
+        }
+        Vec::new()
+    }
+
+    pub fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb88320 & (!(crc & 1)).wrapping_add(1));
+            }
+        }
+        !crc
+    }
+
+    fn put16(bytes: &mut Vec<u8>, value: u16) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn get32(bytes: &[u8], at: usize) -> Option<u32> {
+        let b = bytes.get(at..at.checked_add(4)?)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn field32(bytes: &[u8], at: usize) -> Result<u32> {
+        match get32(bytes, at) {
+            Some(value) => Ok(value),
+            None => bail!(".npz archive is truncated"),
+        }
+    }
+
+    fn field16(bytes: &[u8], at: usize) -> Result<u16> {
+        let b = slice(bytes, at, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn slice(bytes: &[u8], at: usize, len: usize) -> Result<&[u8]> {
+        match at.checked_add(len).and_then(|end| bytes.get(at..end)) {
+            Some(bytes) => Ok(bytes),
+            None => bail!(".npz archive is truncated"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_header() {
+        let array = NpyArray::float(vec![2, 3], vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let bytes = array.to_bytes().unwrap();
+        assert_eq!((bytes.len() - 24) % 64, 0);
+        assert!(String::from_utf8_lossy(&bytes[10..])
+            .starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert_eq!(NpyArray::from_bytes(&bytes).unwrap(), array);
+        assert_eq!(zip::crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn test_npz_roundtrip() {
+        let mut set = EmbeddingSet::default();
+        set.push("alice".into(), vec![0.1, 0.2], Some((0.0, 1.5)));
+        set.push("bob".into(), vec![0.3, 0.4], Some((1.5, 2.0)));
+        set.push("zoë".into(), vec![0.5, 0.6], Some((3.0, 4.0)));
+        let bytes = set.to_npz().unwrap();
+        assert_eq!(EmbeddingSet::from_npz(&bytes).unwrap(), set);
+    }
+
+    #[test]
+    fn test_malformed_npy() {
+        let header = |header: &str| {
+            let mut bytes = NPY_MAGIC.to_vec();
+            bytes.extend_from_slice(&[1, 0]);
+            bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(header.as_bytes());
+            bytes
+        };
+        let error = |bytes: &[u8]| NpyArray::from_bytes(bytes).unwrap_err().to_string();
+
+        let bytes = NpyArray::float(vec![2], vec![1.0, 2.0]).to_bytes().unwrap();
+        assert_eq!(error(&bytes[..40]), ".npy header is truncated");
+        assert_eq!(error(&bytes[..bytes.len() - 1]), ".npy data is truncated");
+        let mut bytes = bytes;
+        bytes[6..12].copy_from_slice(&[2, 0, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(error(&bytes), ".npy header is truncated");
+
+        let bytes = header(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (4611686018427387904, 16), }",
+        );
+        assert!(error(&bytes).contains("is too large"));
+        let bytes =
+            header("{'descr': '<f8', 'fortran_order': False, 'shape': (4611686018427387904,), }");
+        assert_eq!(error(&bytes), ".npy data is truncated");
+        let bytes =
+            header("{'descr': '<U4611686018427387904', 'fortran_order': False, 'shape': (1,), }");
+        assert!(error(&bytes).contains("is too large"));
+    }
+
+    #[test]
+    fn test_malformed_npz() {
+        let mut set = EmbeddingSet::default();
+        set.push("alice".into(), vec![0.1, 0.2], Some((0.0, 1.5)));
+        set.push("bob".into(), vec![0.3, 0.4], None);
+        let bytes = set.to_npz().unwrap();
+
+        // Every truncation and corrupted byte is an error or a valid set, never a panic
+        for len in 0..bytes.len() {
+            assert!(EmbeddingSet::from_npz(&bytes[..len]).is_err());
+        }
+        for i in 0..bytes.len() {
+            for value in [0x00, 0x7f, 0xff] {
+                let mut bytes = bytes.clone();
+                bytes[i] = value;
+                let _ = EmbeddingSet::from_npz(&bytes);
+            }
+        }
+
+        // Two labels for embeddings of dimension 0
+        let entries = [
+            (
+                "embeddings.npy",
+                NpyArray::float(vec![2, 0], vec![]).to_bytes().unwrap(),
+            ),
+            (
+                "labels.npy",
+                NpyArray::strings(vec!["a".into(), "b".into()])
+                    .to_bytes()
+                    .unwrap(),
+            ),
+        ];
+        let bytes = zip::write(&entries).unwrap();
+        assert!(EmbeddingSet::from_npz(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_read_python_archive() {
+        // Written by Python's zipfile the way np.savez does, with zip64 local headers,
+        // holding a float64 array of shape (1, 2)
+        let hex = concat!(
+            "504b03042d000000000000002100fb33f2e6ffffffffffffffff0e001400656d62656464696e67732e6e",
+            "70790100100090000000000000009000000000000000934e554d5059010076007b276465736372273a20",
+            "273c6638272c2027666f727472616e5f6f72646572273a2046616c73652c20277368617065273a202831",
+            "2c2032292c207d2020202020202020202020202020202020202020202020202020202020202020202020",
+            "20202020202020202020202020202020202020202020200a000000000000e03f000000000000d03f504b",
+            "01022d032d000000000000002100fb33f2e690000000900000000e000000000000000000000080010000",
+            "0000656d62656464696e67732e6e7079504b050600000000010001003c000000d00000000000",
+        );
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        let set = EmbeddingSet::from_npz(&bytes).unwrap();
+        assert_eq!(set.embeddings, vec![vec![0.5, 0.25]]);
+        assert_eq!(set.labels, vec!["0"]);
+    }
+}
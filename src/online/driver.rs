@@ -0,0 +1,311 @@
+//! Drive an [`OnlineStream`] with audio chunks and get typed events back.
+//!
+//! ```ignore
+//! let driver = OnlineDriver::new(recognizer, 16000);
+//! for event in driver.events(receiver.iter()) {
+//!     match event {
+//!         Event::Partial(u) => println!("\t{}", u.text),
+//!         Event::Final(u) => println!("[{}] {}", u.segment, u.text),
+//!         Event::KeywordDetected(u) => println!("keyword {}", u.text),
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use super::stream::OnlineStream;
+
+/// Text of a segment together with its position in the audio, in seconds
+/// since the driver was created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utterance {
+    pub segment: usize,
+    pub text: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The running transcript of the current segment changed.
+    Partial(Utterance),
+    /// An endpoint was detected and the segment is complete.
+    Final(Utterance),
+    /// A keyword spotter fired. `start` is the end of the previous detection or skipped audio.
+    KeywordDetected(Utterance),
+}
+
+impl Event {
+    pub fn utterance(&self) -> &Utterance {
+        match self {
+            Event::Partial(u) | Event::Final(u) | Event::KeywordDetected(u) => u,
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, Event::Final(_))
+    }
+}
+
+/// Runs the accept / decode / endpoint / reset loop over an [`OnlineStream`].
+///
+/// Works for both recognizers and keyword spotters, see [`OnlineStream::detects_keywords`].
+pub struct OnlineDriver<S: OnlineStream> {
+    stream: S,
+    sample_rate: i32,
+    segment: usize,
+    last_text: String,
+    samples_fed: u64,
+    segment_start: u64,
+}
+
+impl<S: OnlineStream> OnlineDriver<S> {
+    pub fn new(stream: S, sample_rate: i32) -> Self {
+        Self {
+            stream,
+            sample_rate,
+            segment: 0,
+            last_text: String::new(),
+            samples_fed: 0,
+            segment_start: 0,
+        }
+    }
+
+    /// Feed a chunk of audio and return the events it produced.
+    pub fn accept_waveform(&mut self, samples: Vec<f32>) -> Vec<Event> {
+        self.samples_fed += samples.len() as u64;
+        self.stream.accept_waveform(self.sample_rate, samples);
+        self.decode()
+    }
+
+    /// Advance the clock for audio that was not fed to the stream, e.g. silence
+    /// dropped by a VAD, so event times stay aligned with the input.
+    pub fn skip(&mut self, num_samples: usize) {
+        self.samples_fed += num_samples as u64;
+        if self.last_text.is_empty() {
+            self.segment_start = self.samples_fed;
+        }
+    }
+
+    /// Finish the current segment as if an endpoint was detected.
+    ///
+    /// Returns the final event if the segment has any text. The stream is reset either way.
+    pub fn force_endpoint(&mut self) -> Option<Event> {
+        if self.stream.detects_keywords() {
+            return None;
+        }
+        let text = self.stream.get_result();
+        self.end_segment(text)
+    }
+
+    /// Signal the end of the input and flush the remaining events.
+    pub fn finish(&mut self) -> Vec<Event> {
+        self.stream.input_finished();
+        let mut events = self.decode();
+        events.extend(self.force_endpoint());
+        events
+    }
+
+    /// Turn the driver into an iterator of events over `chunks`.
+    ///
+    /// [`OnlineDriver::finish`] is called once `chunks` is exhausted.
+    pub fn events<I>(self, chunks: I) -> Events<S, I::IntoIter>
+    where
+        I: IntoIterator<Item = Vec<f32>>,
+    {
+        Events {
+            driver: self,
+            chunks: chunks.into_iter(),
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Index of the segment that is currently being decoded.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Seconds of audio fed so far.
+    pub fn elapsed(&self) -> f32 {
+        self.seconds(self.samples_fed)
+    }
+
+    pub fn sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn decode(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        if self.stream.detects_keywords() {
+            // A detection only lasts until the next decode, check after every frame
+            while self.stream.is_ready() {
+                self.stream.decode_stream();
+                let keyword = self.stream.get_result();
+                if !keyword.is_empty() {
+                    events.push(Event::KeywordDetected(self.utterance(keyword)));
+                    self.next_segment();
+                }
+            }
+            return events;
+        }
+
+        while self.stream.is_ready() {
+            self.stream.decode_stream();
+        }
+        let text = self.stream.get_result();
+        if !text.is_empty() && text != self.last_text {
+            self.last_text.clone_from(&text);
+            events.push(Event::Partial(self.utterance(text.clone())));
+        }
+        if self.stream.is_endpoint() {
+            events.extend(self.end_segment(text));
+        }
+        events
+    }
+
+    fn end_segment(&mut self, text: String) -> Option<Event> {
+        let event = if text.is_empty() {
+            None
+        } else {
+            let event = Event::Final(self.utterance(text));
+            self.segment += 1;
+            Some(event)
+        };
+        self.stream.reset();
+        self.last_text.clear();
+        self.segment_start = self.samples_fed;
+        event
+    }
+
+    fn next_segment(&mut self) {
+        self.segment += 1;
+        self.segment_start = self.samples_fed;
+    }
+
+    fn utterance(&self, text: String) -> Utterance {
+        Utterance {
+            segment: self.segment,
+            text,
+            start: self.seconds(self.segment_start),
+            end: self.seconds(self.samples_fed),
+        }
+    }
+
+    fn seconds(&self, samples: u64) -> f32 {
+        samples as f32 / self.sample_rate as f32
+    }
+}
+
+/// Iterator returned by [`OnlineDriver::events`].
+pub struct Events<S: OnlineStream, I> {
+    driver: OnlineDriver<S>,
+    chunks: I,
+    pending: VecDeque<Event>,
+    finished: bool,
+}
+
+impl<S: OnlineStream, I> Events<S, I> {
+    pub fn driver(&self) -> &OnlineDriver<S> {
+        &self.driver
+    }
+
+    pub fn into_driver(self) -> OnlineDriver<S> {
+        self.driver
+    }
+}
+
+impl<S, I> Iterator for Events<S, I>
+where
+    S: OnlineStream,
+    I: Iterator<Item = Vec<f32>>,
+{
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.pending.extend(self.driver.accept_waveform(chunk)),
+                None => {
+                    self.finished = true;
+                    self.pending.extend(self.driver.finish());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::online::mock::MockStream;
+
+    #[test]
+    fn test_partial_and_final_events() {
+        let stream = MockStream::new(["he", "hello", "hello|", "", "world"]);
+        let chunks = (0..5).map(|_| vec![0.0; 1600]);
+        let events: Vec<Event> = OnlineDriver::new(stream, 16000).events(chunks).collect();
+
+        let texts: Vec<(bool, usize, &str)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.is_final(),
+                    e.utterance().segment,
+                    e.utterance().text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                (false, 0, "he"),
+                (false, 0, "hello"),
+                (true, 0, "hello"),
+                (false, 1, "world"),
+                (true, 1, "world"),
+            ]
+        );
+        assert_eq!(events[2].utterance().end, 0.3);
+        assert_eq!(events[3].utterance().start, 0.3);
+    }
+
+    #[test]
+    fn test_keywords_in_multi_frame_chunks() {
+        // One detection per frame, three frames per chunk
+        let spotter = MockStream::new(["", "hey", "", "", "stop", "go"]).spotter(3);
+        let chunks = (0..2).map(|_| vec![0.0; 1600]);
+        let events: Vec<Event> = OnlineDriver::new(spotter, 16000).events(chunks).collect();
+
+        // Neither the detection in the middle of a chunk nor the one right before
+        // another detection is lost
+        let keywords: Vec<(usize, &str)> = events
+            .iter()
+            .map(|e| match e {
+                Event::KeywordDetected(u) => (u.segment, u.text.as_str()),
+                _ => panic!("unexpected event {:?}", e),
+            })
+            .collect();
+        assert_eq!(keywords, vec![(0, "hey"), (1, "stop"), (2, "go")]);
+        assert_eq!(events[1].utterance().start, 0.1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Online recognition gated by voice activity detection.
+//!
+//! Audio only reaches the recognizer while the VAD reports speech, so silence
+//! costs no decoding. When speech ends the current segment is finalized and the
+//! stream is reset.
+
+use std::collections::VecDeque;
+
+use super::{
+    driver::{Event, OnlineDriver},
+    stream::OnlineStream,
+};
+use crate::vad::{Vad, VoiceActivityDetector};
+
+pub struct VadGatedStream<S: OnlineStream, V: VoiceActivityDetector = Vad> {
+    vad: V,
+    driver: OnlineDriver<S>,
+    /// Audio before the VAD triggered, fed first so the start of speech is not lost.
+    pre_roll: VecDeque<f32>,
+    pre_roll_size: usize,
+    /// Samples waiting for a full VAD window.
+    pending: Vec<f32>,
+    speaking: bool,
+}
+
+impl<S: OnlineStream, V: VoiceActivityDetector> VadGatedStream<S, V> {
+    /// Put `vad` in front of `stream`.
+    ///
+    /// `pre_roll` is the number of seconds of audio kept from before speech was
+    /// detected. It should cover the VAD's `min_speech_duration`.
+    pub fn new(vad: V, stream: S, pre_roll: f32) -> Self {
+        let sample_rate = vad.sample_rate();
+        let pre_roll_size = (pre_roll.max(0.0) * sample_rate as f32) as usize;
+        Self {
+            vad,
+            driver: OnlineDriver::new(stream, sample_rate),
+            pre_roll: VecDeque::with_capacity(pre_roll_size),
+            pre_roll_size,
+            pending: Vec::new(),
+            speaking: false,
+        }
+    }
+
+    /// Feed audio of any length and return the recognition events it produced.
+    pub fn accept_waveform(&mut self, samples: &[f32]) -> Vec<Event> {
+        let window_size = self.vad.window_size();
+        self.pending.extend_from_slice(samples);
+
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while self.pending.len() - offset >= window_size {
+            let window = self.pending[offset..offset + window_size].to_vec();
+            offset += window_size;
+            events.extend(self.process_window(window));
+        }
+        self.pending.drain(..offset);
+        events
+    }
+
+    /// Signal the end of the input and flush the remaining events.
+    pub fn finish(&mut self) -> Vec<Event> {
+        let pending = std::mem::take(&mut self.pending);
+        let mut events = Vec::new();
+        if self.speaking {
+            events.extend(self.driver.accept_waveform(pending));
+            events.extend(self.driver.finish());
+        } else {
+            self.driver.skip(pending.len() + self.pre_roll.len());
+        }
+        self.pre_roll.clear();
+        self.speaking = false;
+        events
+    }
+
+    /// Whether the VAD currently reports speech.
+    pub fn is_speech(&self) -> bool {
+        self.speaking
+    }
+
+    pub fn driver(&self) -> &OnlineDriver<S> {
+        &self.driver
+    }
+
+    pub fn into_inner(self) -> (V, S) {
+        (self.vad, self.driver.into_inner())
+    }
+
+    fn process_window(&mut self, window: Vec<f32>) -> Vec<Event> {
+        self.vad.accept_waveform(&window);
+        let speech = self.vad.is_speech();
+        // Only the detection state is used, drop the collected segments
+        self.vad.clear();
+
+        let mut events = Vec::new();
+        match (self.speaking, speech) {
+            (false, true) => {
+                self.speaking = true;
+                let pre_roll: Vec<f32> = self.pre_roll.drain(..).collect();
+                events.extend(self.driver.accept_waveform(pre_roll));
+                events.extend(self.driver.accept_waveform(window));
+            }
+            (true, true) => {
+                events.extend(self.driver.accept_waveform(window));
+            }
+            (true, false) => {
+                // The recognizer has already seen the VAD's min_silence_duration of
+                // trailing silence, so everything spoken is decoded by now.
+                self.speaking = false;
+                events.extend(self.driver.accept_waveform(window));
+                events.extend(self.driver.force_endpoint());
+            }
+            (false, false) => {
+                self.pre_roll.extend(window);
+                let excess = self.pre_roll.len().saturating_sub(self.pre_roll_size);
+                self.pre_roll.drain(..excess);
+                self.driver.skip(excess);
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        online::mock::MockStream,
+        vad::energy::{tone, EnergyVad, EnergyVadConfig},
+    };
+
+    #[test]
+    fn test_gate_with_energy_vad() {
+        let mut samples = vec![0.0; 16000];
+        samples.extend(tone(1.0, 0.3));
+        samples.extend(vec![0.0; 16000]);
+
+        let vad = EnergyVad::new(EnergyVadConfig::default()).unwrap();
+        let mut gated = VadGatedStream::new(vad, MockStream::new(["speech"]), 0.3);
+        let mut events = Vec::new();
+        for chunk in samples.chunks(1000) {
+            events.extend(gated.accept_waveform(chunk));
+        }
+        events.extend(gated.finish());
+
+        // Speech is detected 0.25s into the tone and 0.3s of pre-roll is fed
+        // before it, ending 0.5s into the trailing silence
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].is_final());
+        assert_eq!(events[0].utterance().start, 0.94);
+        assert!(events[1].is_final());
+        assert_eq!(events[1].utterance().end, 2.5);
+        // Skipped silence still advances the clock
+        assert_eq!(gated.driver().elapsed(), 3.0);
+
+        let (_, stream) = gated.into_inner();
+        assert_eq!(stream.samples, 40000 - 15040);
+        assert_eq!(stream.resets, 1);
+    }
+}
@@ -0,0 +1,112 @@
+//! A scripted [`OnlineStream`] shared by the tests of the online modules.
+
+use std::{sync::mpsc, time::Duration};
+
+use super::stream::OnlineStream;
+
+/// Decodes `frames_per_chunk` frames from every chunk of audio. Each frame takes the
+/// next entry of the script as the result, a trailing `|` marks an endpoint. Frames
+/// past the end of the script repeat its last entry.
+pub(crate) struct MockStream {
+    script: Vec<String>,
+    frames_per_chunk: usize,
+    keywords: bool,
+    decode_time: Duration,
+    accepted: Option<mpsc::Sender<usize>>,
+    available: usize,
+    decoded: usize,
+    text: String,
+    endpoint: bool,
+    /// Chunks and samples accepted so far.
+    pub chunks: usize,
+    pub samples: usize,
+    pub resets: usize,
+}
+
+impl MockStream {
+    pub fn new<I, T>(script: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Self {
+            script: script.into_iter().map(Into::into).collect(),
+            frames_per_chunk: 1,
+            keywords: false,
+            decode_time: Duration::ZERO,
+            accepted: None,
+            available: 0,
+            decoded: 0,
+            text: String::new(),
+            endpoint: false,
+            chunks: 0,
+            samples: 0,
+            resets: 0,
+        }
+    }
+
+    /// Act as a keyword spotter, the script holds the detection of each frame.
+    pub fn spotter(mut self, frames_per_chunk: usize) -> Self {
+        self.keywords = true;
+        self.frames_per_chunk = frames_per_chunk;
+        self
+    }
+
+    /// Sleep this long for every decoded frame.
+    pub fn with_decode_time(mut self, decode_time: Duration) -> Self {
+        self.decode_time = decode_time;
+        self
+    }
+
+    /// Send the number of chunks accepted so far after each chunk.
+    #[cfg(feature = "tokio")]
+    pub fn with_accepted(mut self, accepted: mpsc::Sender<usize>) -> Self {
+        self.accepted = Some(accepted);
+        self
+    }
+}
+
+impl OnlineStream for MockStream {
+    fn accept_waveform(&mut self, _sample_rate: i32, samples: Vec<f32>) {
+        self.chunks += 1;
+        self.samples += samples.len();
+        self.available += self.frames_per_chunk;
+        if let Some(accepted) = &self.accepted {
+            let _ = accepted.send(self.chunks);
+        }
+    }
+
+    fn decode_stream(&mut self) {
+        std::thread::sleep(self.decode_time);
+        let step = self
+            .script
+            .get(self.decoded)
+            .or(self.script.last())
+            .map_or("", String::as_str);
+        self.decoded += 1;
+        self.endpoint = step.ends_with('|');
+        self.text = step.trim_end_matches('|').to_string();
+    }
+
+    fn get_result(&mut self) -> String {
+        self.text.clone()
+    }
+
+    fn is_ready(&mut self) -> bool {
+        self.decoded < self.available
+    }
+
+    fn is_endpoint(&mut self) -> bool {
+        self.endpoint
+    }
+
+    fn reset(&mut self) {
+        self.text.clear();
+        self.endpoint = false;
+        self.resets += 1;
+    }
+
+    fn detects_keywords(&self) -> bool {
+        self.keywords
+    }
+}
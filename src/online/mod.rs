@@ -1,4 +1,11 @@
+pub mod driver;
+pub mod gated;
+#[cfg(test)]
+mod mock;
 pub mod paraformer;
+#[cfg(feature = "tokio")]
+pub mod session;
+pub mod simulate;
 pub mod stream;
 pub mod transducer;
 pub mod zipformer2_ctc;
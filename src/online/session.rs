@@ -0,0 +1,203 @@
+//! Async streaming recognition on top of tokio.
+//!
+//! Decoding runs on a dedicated blocking worker. Audio is queued through a bounded
+//! channel, so [`AudioSink::send`] waits once `capacity` chunks are pending and
+//! producers are slowed down to the decoding speed.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use eyre::{bail, Result};
+use futures_core::Stream;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use super::{
+    driver::{Event, OnlineDriver},
+    stream::OnlineStream,
+};
+
+/// Start a session decoding `stream` on a blocking worker.
+///
+/// `capacity` is the number of audio chunks that may be queued before
+/// [`AudioSink::send`] waits. Must be called from within a tokio runtime.
+pub fn spawn<S>(stream: S, sample_rate: i32, capacity: usize) -> (AudioSink, EventStream<S>)
+where
+    S: OnlineStream + Send + 'static,
+{
+    let (audio_tx, mut audio_rx) = mpsc::channel::<Message>(capacity.max(1));
+    let (event_tx, event_rx) = mpsc::channel::<Event>(capacity.max(1));
+
+    let worker = tokio::task::spawn_blocking(move || {
+        let mut driver = OnlineDriver::new(stream, sample_rate);
+        while let Some(Message::Audio(samples)) = audio_rx.blocking_recv() {
+            if event_tx.is_closed() {
+                return driver.into_inner();
+            }
+            for event in driver.accept_waveform(samples) {
+                if event_tx.blocking_send(event).is_err() {
+                    // Nobody listens for events anymore
+                    return driver.into_inner();
+                }
+            }
+        }
+        if event_tx.is_closed() {
+            return driver.into_inner();
+        }
+        for event in driver.finish() {
+            if event_tx.blocking_send(event).is_err() {
+                break;
+            }
+        }
+        driver.into_inner()
+    });
+
+    let events = EventStream {
+        events: event_rx,
+        audio: audio_tx.downgrade(),
+        worker,
+    };
+    let sink = AudioSink { audio: audio_tx };
+    (sink, events)
+}
+
+#[derive(Debug)]
+enum Message {
+    Audio(Vec<f32>),
+    /// Sent by [`EventStream::join`] to stop the worker while sinks are alive.
+    Stop,
+}
+
+/// Sending half of a session. Drop every clone of it to finish the session.
+#[derive(Debug, Clone)]
+pub struct AudioSink {
+    audio: mpsc::Sender<Message>,
+}
+
+impl AudioSink {
+    /// Queue a chunk of audio, waiting while the worker is behind.
+    pub async fn send(&self, samples: Vec<f32>) -> Result<()> {
+        if self.audio.send(Message::Audio(samples)).await.is_err() {
+            bail!("recognition session is closed");
+        }
+        Ok(())
+    }
+
+    /// Queue a chunk of audio without waiting. Fails if the queue is full.
+    pub fn try_send(&self, samples: Vec<f32>) -> Result<()> {
+        match self.audio.try_send(Message::Audio(samples)) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => bail!("recognition session is busy"),
+            Err(mpsc::error::TrySendError::Closed(_)) => bail!("recognition session is closed"),
+        }
+    }
+
+    /// Queue a chunk of audio from a synchronous context such as an audio callback thread.
+    ///
+    /// Must not be called from within the async runtime.
+    pub fn blocking_send(&self, samples: Vec<f32>) -> Result<()> {
+        if self.audio.blocking_send(Message::Audio(samples)).is_err() {
+            bail!("recognition session is closed");
+        }
+        Ok(())
+    }
+}
+
+/// Receiving half of a session, a [`Stream`] of [`Event`]s.
+///
+/// The stream ends after the last [`AudioSink`] is dropped and the remaining audio is decoded.
+#[derive(Debug)]
+pub struct EventStream<S> {
+    events: mpsc::Receiver<Event>,
+    /// Doesn't keep the session open, only lets `join` reach the worker.
+    audio: mpsc::WeakSender<Message>,
+    worker: JoinHandle<S>,
+}
+
+impl<S> EventStream<S> {
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    /// Stop listening and get the online stream back once the worker exits.
+    ///
+    /// Audio that is still queued is discarded and the remaining [`AudioSink`]s
+    /// are closed, so this doesn't wait for them to be dropped.
+    pub async fn join(self) -> Result<S> {
+        drop(self.events);
+        if let Some(audio) = self.audio.upgrade() {
+            // Wakes the worker if it waits for audio, fails if it already exited
+            let _ = audio.send(Message::Stop).await;
+        }
+        match self.worker.await {
+            Ok(stream) => Ok(stream),
+            Err(err) => bail!("recognition worker failed: {}", err),
+        }
+    }
+}
+
+impl<S> Stream for EventStream<S> {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::online::mock::MockStream;
+
+    /// Reports the number of chunks seen as the text.
+    fn stream() -> MockStream {
+        MockStream::new((1..=10).map(|i| i.to_string()))
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_backpressure_and_finish() {
+        runtime().block_on(async {
+            let (accepted_tx, accepted) = std::sync::mpsc::channel();
+            let stream = stream().with_accepted(accepted_tx);
+            let (sink, mut events) = spawn(stream, 16000, 1);
+
+            // Nobody reads events: the first one fills the event queue and the worker
+            // blocks on the second, so one more chunk fits in the audio queue
+            sink.try_send(vec![0.0; 160]).unwrap();
+            assert_eq!(accepted.recv().unwrap(), 1);
+            sink.try_send(vec![0.0; 160]).unwrap();
+            assert_eq!(accepted.recv().unwrap(), 2);
+            sink.try_send(vec![0.0; 160]).unwrap();
+            let busy = sink.try_send(vec![0.0; 160]).unwrap_err();
+            assert_eq!(busy.to_string(), "recognition session is busy");
+
+            drop(sink);
+            let mut received = Vec::new();
+            while let Some(event) = events.recv().await {
+                received.push((event.is_final(), event.utterance().text.clone()));
+            }
+            // One partial per chunk, then the final one from `finish`
+            let expected = [(false, "1"), (false, "2"), (false, "3"), (true, "3")];
+            assert_eq!(received, expected.map(|(f, t)| (f, t.to_string())));
+            assert_eq!(events.join().await.unwrap().chunks, 3);
+        });
+    }
+
+    #[test]
+    fn test_join_with_live_sink() {
+        runtime().block_on(async {
+            let (sink, events) = spawn(stream(), 16000, 4);
+            sink.send(vec![0.0; 160]).await.unwrap();
+            let stream = events.join().await.unwrap();
+            assert!(stream.chunks <= 1);
+            assert!(sink.send(vec![0.0; 160]).await.is_err());
+        });
+    }
+}
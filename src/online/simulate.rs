@@ -0,0 +1,252 @@
+//! Replay recorded audio into an [`OnlineStream`] as if it came from a microphone
+//! and measure how quickly results appear.
+//!
+//! Latencies are measured from the moment a chunk is delivered, i.e. when it would
+//! have been fully recorded by a live microphone (scaled by the [`Pace`]).
+//! Speech boundaries are estimated with a simple energy threshold on each chunk.
+
+use std::time::{Duration, Instant};
+
+use eyre::{bail, Result};
+
+use super::{
+    driver::{Event, OnlineDriver},
+    stream::OnlineStream,
+};
+use crate::read_audio_file;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// Deliver audio at the speed it was recorded.
+    RealTime,
+    /// Deliver audio this many times faster than real time.
+    Accelerated(f32),
+    /// Deliver the next chunk as soon as the previous one is processed.
+    AsFastAsPossible,
+}
+
+impl Pace {
+    fn speed(&self) -> Option<f32> {
+        match self {
+            Pace::RealTime => Some(1.0),
+            Pace::Accelerated(speed) => Some(*speed),
+            Pace::AsFastAsPossible => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    /// Samples per chunk, e.g. 1600 for 100 ms at 16 kHz.
+    pub chunk_size: usize,
+    pub pace: Pace,
+    /// A chunk with RMS at or above this is considered speech when estimating
+    /// where a segment starts and ends.
+    pub speech_rms: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentLatency {
+    pub segment: usize,
+    pub text: String,
+    /// From delivery of the first speech chunk of the segment to its first partial result.
+    pub first_partial: Option<Duration>,
+    /// From delivery of the last speech chunk of the segment to its final result,
+    /// including the trailing silence needed by the endpoint rules.
+    pub final_result: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencyReport {
+    pub segments: Vec<SegmentLatency>,
+    pub events: Vec<Event>,
+    pub audio_duration: Duration,
+    /// Time spent decoding, without waiting for audio.
+    pub processing_time: Duration,
+    pub wall_time: Duration,
+}
+
+impl LatencyReport {
+    /// Processing time divided by audio duration. Below 1.0 keeps up with live audio.
+    pub fn real_time_factor(&self) -> f32 {
+        if self.audio_duration.is_zero() {
+            return 0.0;
+        }
+        self.processing_time.as_secs_f32() / self.audio_duration.as_secs_f32()
+    }
+
+    pub fn mean_first_partial(&self) -> Option<Duration> {
+        mean(self.segments.iter().filter_map(|s| s.first_partial))
+    }
+
+    pub fn mean_final_result(&self) -> Option<Duration> {
+        mean(self.segments.iter().filter_map(|s| s.final_result))
+    }
+}
+
+/// Per-segment bookkeeping while the simulation runs.
+#[derive(Default)]
+struct SegmentState {
+    speech_start: Option<Instant>,
+    speech_end: Option<Instant>,
+    first_partial: Option<Duration>,
+}
+
+impl Simulator {
+    pub fn new(chunk_size: usize, pace: Pace) -> Self {
+        Self {
+            chunk_size: chunk_size.max(1),
+            pace,
+            speech_rms: 0.01,
+        }
+    }
+
+    /// Replay a 16 kHz wav file into `stream`.
+    pub fn run_file<S: OnlineStream>(&self, stream: S, path: &str) -> Result<(S, LatencyReport)> {
+        let (sample_rate, samples) = read_audio_file(path)?;
+        self.run(stream, sample_rate, &samples)
+    }
+
+    /// Replay `samples` into `stream` and return it together with the measurements.
+    pub fn run<S: OnlineStream>(
+        &self,
+        stream: S,
+        sample_rate: i32,
+        samples: &[f32],
+    ) -> Result<(S, LatencyReport)> {
+        if sample_rate <= 0 {
+            bail!("sample rate must be positive, got {}", sample_rate);
+        }
+        if let Pace::Accelerated(speed) = self.pace {
+            if !(speed > 0.0 && speed.is_finite()) {
+                bail!("speed must be positive, got {}", speed);
+            }
+        }
+        let mut driver = OnlineDriver::new(stream, sample_rate);
+        let chunk_duration = Duration::from_secs_f32(self.chunk_size as f32 / sample_rate as f32);
+
+        let mut events = Vec::new();
+        let mut segments = Vec::new();
+        let mut state = SegmentState::default();
+        let mut processing_time = Duration::ZERO;
+        let start = Instant::now();
+
+        for (i, chunk) in samples.chunks(self.chunk_size).enumerate() {
+            // A live microphone delivers the chunk once it is fully recorded
+            if let Some(speed) = self.pace.speed() {
+                let due = start + chunk_duration.mul_f32((i + 1) as f32 / speed);
+                let now = Instant::now();
+                if due > now {
+                    std::thread::sleep(due - now);
+                }
+            }
+            let delivered = Instant::now();
+            if rms(chunk) >= self.speech_rms {
+                state.speech_start.get_or_insert(delivered);
+                state.speech_end = Some(delivered);
+            }
+
+            let chunk_events = driver.accept_waveform(chunk.to_vec());
+            let handled = Instant::now();
+            processing_time += handled - delivered;
+            self.record(&chunk_events, handled, &mut state, &mut segments);
+            events.extend(chunk_events);
+        }
+
+        let finishing = Instant::now();
+        let chunk_events = driver.finish();
+        let handled = Instant::now();
+        processing_time += handled - finishing;
+        self.record(&chunk_events, handled, &mut state, &mut segments);
+        events.extend(chunk_events);
+
+        let report = LatencyReport {
+            segments,
+            events,
+            audio_duration: Duration::from_secs_f32(samples.len() as f32 / sample_rate as f32),
+            processing_time,
+            wall_time: start.elapsed(),
+        };
+        Ok((driver.into_inner(), report))
+    }
+
+    fn record(
+        &self,
+        events: &[Event],
+        at: Instant,
+        state: &mut SegmentState,
+        segments: &mut Vec<SegmentLatency>,
+    ) {
+        for event in events {
+            match event {
+                Event::Partial(_) => {
+                    if state.first_partial.is_none() {
+                        state.first_partial = state.speech_start.map(|t| at - t);
+                    }
+                }
+                Event::Final(utterance) | Event::KeywordDetected(utterance) => {
+                    segments.push(SegmentLatency {
+                        segment: utterance.segment,
+                        text: utterance.text.clone(),
+                        first_partial: state.first_partial,
+                        final_result: state.speech_end.map(|t| at - t),
+                    });
+                    *state = SegmentState::default();
+                }
+            }
+        }
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = samples.iter().map(|s| s * s).sum();
+    (sum / samples.len() as f32).sqrt()
+}
+
+fn mean<I: Iterator<Item = Duration>>(durations: I) -> Option<Duration> {
+    let (sum, count) = durations.fold((Duration::ZERO, 0u32), |(sum, n), d| (sum + d, n + 1));
+    (count > 0).then(|| sum / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::online::mock::MockStream;
+
+    /// Hears "hello" in the loud chunks of the samples below and detects an endpoint
+    /// at the first quiet chunk after them. Each chunk takes 2 ms to decode.
+    fn stream() -> MockStream {
+        MockStream::new(["", "", "hello", "hello", "hello", "hello|", ""])
+            .with_decode_time(Duration::from_millis(2))
+    }
+
+    #[test]
+    fn test_latency_bookkeeping() {
+        let mut samples = vec![0.0; 3200];
+        samples.extend(vec![0.5; 4800]);
+        samples.extend(vec![0.0; 3200]);
+
+        let simulator = Simulator::new(1600, Pace::AsFastAsPossible);
+        let (_, report) = simulator.run(stream(), 16000, &samples).unwrap();
+        assert_eq!(report.events.len(), 2);
+        assert_eq!(report.segments.len(), 1);
+        let segment = &report.segments[0];
+        assert_eq!((segment.segment, segment.text.as_str()), (0, "hello"));
+        // Decoding the first loud chunk and the quiet chunk ending the segment
+        assert!(segment.first_partial.unwrap() >= Duration::from_millis(2));
+        assert!(segment.final_result.unwrap() >= Duration::from_millis(2));
+
+        assert!((report.audio_duration.as_secs_f32() - 0.7).abs() < 1e-4);
+        assert!(report.processing_time >= Duration::from_millis(14));
+        assert!(report.processing_time <= report.wall_time);
+        let rtf = report.processing_time.as_secs_f32() / report.audio_duration.as_secs_f32();
+        assert!((report.real_time_factor() - rtf).abs() < 1e-6);
+
+        let paced = Simulator::new(1600, Pace::Accelerated(0.0));
+        assert!(paced.run(stream(), 16000, &samples).is_err());
+        assert!(simulator.run(stream(), 0, &samples).is_err());
+    }
+}
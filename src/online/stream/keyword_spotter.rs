@@ -3,6 +3,7 @@ use std::{
     path::Path,
 };
 
+use eyre::{bail, Result};
 use sherpa_rs_sys::{
     SherpaOnnxFeatureConfig, SherpaOnnxKeywordSpotterConfig, SherpaOnnxOnlineModelConfig,
 };
@@ -11,6 +12,95 @@ use crate::{get_default_provider, online::transducer::Transducer};
 
 use super::OnlineStream;
 
+/// A keyword entry for [`KeywordSpottingStream`].
+///
+/// `tokens` are the model units of the keyword separated by spaces, e.g.
+/// `x iǎo ài t óng x ué` for a pinyin model or `▁HE LLO ▁WORLD` for a BPE model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyword {
+    pub tokens: String,
+    /// Text reported by the spotter when the keyword fires.
+    pub phrase: Option<String>,
+    /// Boosting score, overrides the spotter's `keywords_score`.
+    pub boost: Option<f32>,
+    /// Trigger threshold, overrides the spotter's `keywords_threshold`.
+    pub threshold: Option<f32>,
+}
+
+impl Keyword {
+    pub fn new<T: Into<String>>(tokens: T) -> Self {
+        Self {
+            tokens: tokens.into(),
+            phrase: None,
+            boost: None,
+            threshold: None,
+        }
+    }
+
+    pub fn with_phrase<T: Into<String>>(mut self, phrase: T) -> Self {
+        self.phrase = Some(phrase.into());
+        self
+    }
+
+    pub fn with_boost(mut self, boost: f32) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Format the keyword as a line of a sherpa-onnx keywords file:
+    /// `tokens :boost #threshold @phrase`
+    pub fn to_line(&self) -> Result<String> {
+        let tokens = self.tokens.split_whitespace().collect::<Vec<_>>().join(" ");
+        if tokens.is_empty() {
+            bail!("keyword has no tokens");
+        }
+        if tokens.contains(['/', ':', '#', '@']) {
+            bail!("keyword tokens `{}` contain a reserved character", tokens);
+        }
+
+        let mut line = tokens;
+        if let Some(boost) = self.boost {
+            line.push_str(&format!(" :{}", boost));
+        }
+        if let Some(threshold) = self.threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                bail!(
+                    "keyword threshold must be between 0 and 1, got {}",
+                    threshold
+                );
+            }
+            line.push_str(&format!(" #{}", threshold));
+        }
+        if let Some(phrase) = &self.phrase {
+            if phrase.is_empty() || phrase.contains(char::is_whitespace) || phrase.contains('/') {
+                bail!(
+                    "keyword phrase `{}` must be a single word without `/`",
+                    phrase
+                );
+            }
+            line.push_str(&format!(" @{}", phrase));
+        }
+        Ok(line)
+    }
+}
+
+/// Join keywords into the `/` separated form accepted by the spotter.
+fn keywords_to_string(keywords: &[Keyword]) -> Result<String> {
+    if keywords.is_empty() {
+        bail!("keyword list is empty");
+    }
+    let lines = keywords
+        .iter()
+        .map(Keyword::to_line)
+        .collect::<Result<Vec<_>>>()?;
+    Ok(lines.join("/"))
+}
+
 pub struct KeywordSpottingStream {
     spotter: *mut sherpa_rs_sys::SherpaOnnxKeywordSpotter,
     stream: *mut sherpa_rs_sys::SherpaOnnxOnlineStream,
@@ -25,8 +115,8 @@ impl KeywordSpottingStream {
         file: &Path,
         num_threads: Option<i32>,
 
-        keywords: Option<&str>,
-    ) -> Self {
+        keywords: Option<&[Keyword]>,
+    ) -> Result<Self> {
         let tokens_c = CString::new(tokens.to_str().unwrap()).unwrap();
         let provider_c = CString::new(provider.unwrap_or(get_default_provider())).unwrap();
         let model_type = transducer.model_type();
@@ -43,7 +133,12 @@ impl KeywordSpottingStream {
         Self::new(model_config, file, keywords)
     }
 
-    pub fn new(model: SherpaOnnxOnlineModelConfig, file: &Path, keywords: Option<&str>) -> Self {
+    pub fn new(
+        model: SherpaOnnxOnlineModelConfig,
+        file: &Path,
+        keywords: Option<&[Keyword]>,
+    ) -> Result<Self> {
+        let keywords = keywords.map(keywords_to_string).transpose()?;
         let files_c = CString::new(file.to_str().unwrap()).unwrap();
 
         let mut config = unsafe { std::mem::zeroed::<SherpaOnnxKeywordSpotterConfig>() };
@@ -57,19 +152,77 @@ impl KeywordSpottingStream {
         config.keywords_threshold = 0.25;
         config.keywords_file = files_c.into_raw();
         let spotter = unsafe { sherpa_rs_sys::SherpaOnnxCreateKeywordSpotter(&config) };
+        if spotter.is_null() {
+            bail!("Failed to create keyword spotter");
+        }
 
-        let stream = if let Some(keywords) = keywords {
-            let keywords = std::ffi::CString::new(keywords).unwrap();
-            unsafe {
-                sherpa_rs_sys::SherpaOnnxCreateKeywordStreamWithKeywords(spotter, keywords.as_ptr())
+        let stream = match keywords {
+            Some(keywords) => Self::create_stream(spotter, &keywords),
+            None => Self::create_default_stream(spotter),
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                unsafe { sherpa_rs_sys::SherpaOnnxDestroyKeywordSpotter(spotter) };
+                return Err(err);
             }
-        } else {
-            unsafe { sherpa_rs_sys::SherpaOnnxCreateKeywordStream(spotter) }
         };
-        Self { spotter, stream }
+        Ok(Self { spotter, stream })
+    }
+
+    /// Replace the keyword set on the live spotter.
+    ///
+    /// A new stream is created with `keywords` while the loaded model is kept.
+    /// Audio buffered in the previous stream is discarded.
+    pub fn set_keywords(&mut self, keywords: &[Keyword]) -> Result<()> {
+        let keywords = keywords_to_string(keywords)?;
+        let stream = Self::create_stream(self.spotter, &keywords)?;
+        self.replace_stream(stream);
+        Ok(())
+    }
+
+    /// Go back to the keywords from the keywords file the spotter was created with.
+    pub fn reset_keywords(&mut self) -> Result<()> {
+        let stream = Self::create_default_stream(self.spotter)?;
+        self.replace_stream(stream);
+        Ok(())
+    }
+
+    fn replace_stream(&mut self, stream: *mut sherpa_rs_sys::SherpaOnnxOnlineStream) {
+        unsafe { sherpa_rs_sys::SherpaOnnxDestroyOnlineStream(self.stream) };
+        self.stream = stream;
+    }
+
+    fn create_stream(
+        spotter: *mut sherpa_rs_sys::SherpaOnnxKeywordSpotter,
+        keywords: &str,
+    ) -> Result<*mut sherpa_rs_sys::SherpaOnnxOnlineStream> {
+        let keywords_c = CString::new(keywords)?;
+        let stream = unsafe {
+            sherpa_rs_sys::SherpaOnnxCreateKeywordStreamWithKeywords(spotter, keywords_c.as_ptr())
+        };
+        if stream.is_null() {
+            bail!(
+                "Failed to create keyword stream with keywords `{}`",
+                keywords
+            );
+        }
+        Ok(stream)
+    }
+
+    fn create_default_stream(
+        spotter: *mut sherpa_rs_sys::SherpaOnnxKeywordSpotter,
+    ) -> Result<*mut sherpa_rs_sys::SherpaOnnxOnlineStream> {
+        let stream = unsafe { sherpa_rs_sys::SherpaOnnxCreateKeywordStream(spotter) };
+        if stream.is_null() {
+            bail!("Failed to create keyword stream");
+        }
+        Ok(stream)
     }
 }
 
+unsafe impl Send for KeywordSpottingStream {}
+
 impl Drop for KeywordSpottingStream {
     fn drop(&mut self) {
         unsafe {
@@ -110,4 +263,47 @@ impl OnlineStream for KeywordSpottingStream {
             keyword
         }
     }
+
+    fn input_finished(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxOnlineStreamInputFinished(self.stream);
+        }
+    }
+
+    fn detects_keywords(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_line() {
+        let keyword = Keyword::new("x iǎo  ài")
+            .with_boost(1.5)
+            .with_threshold(0.3)
+            .with_phrase("小爱");
+        assert_eq!(keyword.to_line().unwrap(), "x iǎo ài :1.5 #0.3 @小爱");
+        assert_eq!(Keyword::new("▁HE LLO").to_line().unwrap(), "▁HE LLO");
+
+        assert!(Keyword::new(" ").to_line().is_err());
+        for reserved in ["a/b", "a:b", "a#b", "a@b"] {
+            assert!(Keyword::new(reserved).to_line().is_err(), "{}", reserved);
+        }
+        for threshold in [-0.1, 1.1, f32::NAN] {
+            let keyword = Keyword::new("a").with_threshold(threshold);
+            assert!(keyword.to_line().is_err(), "{}", threshold);
+        }
+        for phrase in ["", "hey you", "a/b"] {
+            let keyword = Keyword::new("a").with_phrase(phrase);
+            assert!(keyword.to_line().is_err(), "{:?}", phrase);
+        }
+        assert_eq!(
+            keywords_to_string(&[Keyword::new("a"), Keyword::new("b c")]).unwrap(),
+            "a/b c"
+        );
+        assert!(keywords_to_string(&[]).is_err());
+    }
 }
@@ -1,10 +1,6 @@
 pub mod keyword_spotter;
 pub mod recognizer;
 
-use std::ffi::CStr;
-
-use sherpa_rs_sys::{SherpaOnnxOnlineStreamIsEndpoint, SherpaOnnxOnlineStreamReset};
-
 pub trait OnlineStream {
     fn accept_waveform(&mut self, sample_rate: i32, samples: Vec<f32>);
     fn decode_stream(&mut self);
@@ -17,4 +13,12 @@ pub trait OnlineStream {
     }
     /// better only use for recognizer
     fn reset(&mut self) {}
+
+    /// Signal that no more audio will be accepted, so the tail can be decoded.
+    fn input_finished(&mut self) {}
+
+    /// Whether results are keyword detections rather than a running transcript.
+    fn detects_keywords(&self) -> bool {
+        false
+    }
 }
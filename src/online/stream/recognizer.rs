@@ -1,10 +1,13 @@
 use std::{
     ffi::{CStr, CString},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use eyre::{bail, Result};
 use sherpa_rs_sys::{
-    SherpaOnnxCreateOnlineRecognizer, SherpaOnnxCreateOnlineStream, SherpaOnnxFeatureConfig,
+    SherpaOnnxCreateOnlineRecognizer, SherpaOnnxCreateOnlineStream,
+    SherpaOnnxCreateOnlineStreamWithHotwords, SherpaOnnxFeatureConfig,
     SherpaOnnxOnlineCtcFstDecoderConfig, SherpaOnnxOnlineModelConfig,
     SherpaOnnxOnlineRecognizerConfig, SherpaOnnxOnlineStreamIsEndpoint,
     SherpaOnnxOnlineStreamReset,
@@ -13,11 +16,12 @@ use sherpa_rs_sys::{
 use crate::{
     get_default_provider,
     online::{paraformer::Paraformer, transducer::Transducer, zipformer2_ctc::Zipformer2Ctc},
+    read_tokens,
 };
 
 use super::OnlineStream;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Search {
     Greedy,
     Beam,
@@ -32,8 +36,119 @@ impl Search {
     }
 }
 
-pub struct RecognizerStream {
+/// HLG graph decoding of a zipformer CTC model, see [`RecognizerStream::from_zipformer`].
+///
+/// Without a graph the CTC output is decoded directly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CtcFstConfig {
+    pub graph: Option<PathBuf>,
+    /// Maximum number of active states while decoding the graph, 3000 by default.
+    pub max_active: Option<i32>,
+}
+
+impl CtcFstConfig {
+    pub fn new<P: Into<PathBuf>>(graph: P) -> Self {
+        Self {
+            graph: Some(graph.into()),
+            max_active: None,
+        }
+    }
+
+    pub fn with_max_active(mut self, max_active: i32) -> Self {
+        self.max_active = Some(max_active);
+        self
+    }
+
+    fn graph_and_max_active(&self) -> Result<Option<(&Path, i32)>> {
+        let Some(graph) = &self.graph else {
+            if let Some(max_active) = self.max_active {
+                bail!("max_active {} is set without an HLG graph", max_active)
+            }
+            return Ok(None);
+        };
+        if !graph.exists() {
+            bail!("HLG graph not found at {}", graph.display())
+        }
+        let max_active = self.max_active.unwrap_or(3000);
+        if max_active <= 0 {
+            bail!("max_active must be positive, got {}", max_active)
+        }
+        Ok(Some((graph, max_active)))
+    }
+}
+
+/// A hotword for contextual biasing of a single stream.
+///
+/// The recognizer is created with the `cjkchar` modeling unit, so it only splits
+/// CJK text into characters itself. For other models `text` must be the model's
+/// tokens separated by spaces, e.g. `▁HE LLO`, see [`crate::text2token`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotword {
+    pub text: String,
+    /// Boosting score, overrides the recognizer's `hotwords_score`.
+    pub score: Option<f32>,
+}
+
+impl Hotword {
+    pub fn new<T: Into<String>>(text: T) -> Self {
+        Self {
+            text: text.into(),
+            score: None,
+        }
+    }
+
+    pub fn with_score(mut self, score: f32) -> Self {
+        self.score = Some(score);
+        self
+    }
+
+    /// Format the hotword as a line of a sherpa-onnx hotwords file: `text :score`
+    pub fn to_line(&self) -> Result<String> {
+        let text = self.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            bail!("hotword is empty");
+        }
+        if text.contains(['/', ':']) {
+            bail!("hotword `{}` contains a reserved character", text);
+        }
+        Ok(match self.score {
+            Some(score) => format!("{} :{}", text, score),
+            None => text,
+        })
+    }
+}
+
+/// Current result of a [`RecognizerStream`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineResult {
+    pub text: String,
+    pub tokens: Vec<String>,
+    /// Start of each token in seconds since the segment started, i.e. since the
+    /// last reset. Empty if the model doesn't report them.
+    pub timestamps: Vec<f32>,
+}
+
+/// Recognizer shared by all the streams created from it.
+struct Recognizer {
     recognizer: *mut sherpa_rs_sys::SherpaOnnxOnlineRecognizer,
+    /// Per-stream hotwords need a transducer with modified beam search;
+    /// other models abort the process when asked for them.
+    supports_hotwords: bool,
+}
+
+unsafe impl Send for Recognizer {}
+unsafe impl Sync for Recognizer {}
+
+impl Drop for Recognizer {
+    fn drop(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxDestroyOnlineRecognizer(self.recognizer);
+        }
+    }
+}
+
+pub struct RecognizerStream {
+    recognizer: Arc<Recognizer>,
     stream: *mut sherpa_rs_sys::SherpaOnnxOnlineStream,
 }
 
@@ -51,9 +166,10 @@ impl RecognizerStream {
         num_threads: Option<i32>,
         hotwords: Option<&Path>,
         hotwords_score: Option<f32>,
-    ) -> Self {
+    ) -> Result<Self> {
         let tokens_c = CString::new(tokens.to_str().unwrap()).unwrap();
         let provider_c = CString::new(provider.unwrap_or(get_default_provider())).unwrap();
+        let supports_hotwords = search == Search::Beam;
 
         let mut model_config = unsafe { std::mem::zeroed::<SherpaOnnxOnlineModelConfig>() };
         model_config.transducer = transducer.as_config();
@@ -84,9 +200,8 @@ impl RecognizerStream {
         }
 
         let recognizer = unsafe { SherpaOnnxCreateOnlineRecognizer(&rec_config) };
-        let stream = unsafe { SherpaOnnxCreateOnlineStream(recognizer) };
         // let display = unsafe { SherpaOnnxCreateDisplay()}
-        Self { recognizer, stream }
+        Self::from_recognizer(recognizer, supports_hotwords)
     }
 
     pub fn from_paraformer(
@@ -99,7 +214,7 @@ impl RecognizerStream {
         num_threads: Option<i32>,
         hotwords: Option<&Path>,
         hotwords_score: Option<f32>,
-    ) -> Self {
+    ) -> Result<Self> {
         let tokens_c = CString::new(tokens.to_str().unwrap()).unwrap();
         let provider_c = CString::new(provider.unwrap_or(get_default_provider())).unwrap();
 
@@ -133,11 +248,10 @@ impl RecognizerStream {
         }
 
         let recognizer = unsafe { SherpaOnnxCreateOnlineRecognizer(&rec_config) };
-        let stream = unsafe { SherpaOnnxCreateOnlineStream(recognizer) };
         // let display = unsafe { SherpaOnnxCreateDisplay()}
 
-        println!("recognizer: {:?}, stream: {:?}", recognizer, stream);
-        Self { recognizer, stream }
+        log::debug!("recognizer: {:?}", recognizer);
+        Self::from_recognizer(recognizer, false)
     }
 
     pub fn from_zipformer(
@@ -148,13 +262,12 @@ impl RecognizerStream {
         debug: bool,
 
         num_threads: Option<i32>,
-        graph: Option<&Path>,
+        fst: CtcFstConfig,
         hotwords: Option<&Path>,
         hotwords_score: Option<f32>,
-    ) -> Self {
+    ) -> Result<Self> {
         let tokens_c = CString::new(tokens.to_str().unwrap()).unwrap();
         let provider_c = CString::new(provider.unwrap_or(get_default_provider())).unwrap();
-        let graph_c = CString::new(graph.unwrap().to_str().unwrap()).unwrap();
 
         let mut model_config = unsafe { std::mem::zeroed::<SherpaOnnxOnlineModelConfig>() };
         // model_config.model_type = zipformer.model_type().into_raw();
@@ -177,10 +290,14 @@ impl RecognizerStream {
         rec_config.rule1_min_trailing_silence = 2.4;
         rec_config.rule2_min_trailing_silence = 1.2;
         rec_config.rule3_min_utterance_length = 300.0;
-        rec_config.ctc_fst_decoder_config = SherpaOnnxOnlineCtcFstDecoderConfig {
-            graph: graph_c.into_raw(),
-            max_active: 3000,
-        };
+
+        if let Some((graph, max_active)) = fst.graph_and_max_active()? {
+            let graph_c = CString::new(graph.to_str().unwrap())?;
+            rec_config.ctc_fst_decoder_config = SherpaOnnxOnlineCtcFstDecoderConfig {
+                graph: graph_c.into_raw(),
+                max_active,
+            };
+        }
 
         if hotwords.is_some() {
             let hotwords_c =
@@ -190,11 +307,108 @@ impl RecognizerStream {
         }
 
         let recognizer = unsafe { SherpaOnnxCreateOnlineRecognizer(&rec_config) };
-        let stream = unsafe { SherpaOnnxCreateOnlineStream(recognizer) };
         // let display = unsafe { SherpaOnnxCreateDisplay()}
 
-        println!("recognizer: {:?}, stream: {:?}", recognizer, stream);
-        Self { recognizer, stream }
+        log::debug!("recognizer: {:?}", recognizer);
+        Self::from_recognizer(recognizer, false)
+    }
+
+    fn from_recognizer(
+        recognizer: *mut sherpa_rs_sys::SherpaOnnxOnlineRecognizer,
+        supports_hotwords: bool,
+    ) -> Result<Self> {
+        if recognizer.is_null() {
+            bail!("Failed to create online recognizer");
+        }
+        // Destroys the recognizer if the stream can't be created
+        let recognizer = Arc::new(Recognizer {
+            recognizer,
+            supports_hotwords,
+        });
+        let stream = unsafe { SherpaOnnxCreateOnlineStream(recognizer.recognizer) };
+        if stream.is_null() {
+            bail!("Failed to create online stream");
+        }
+        Ok(Self { recognizer, stream })
+    }
+
+    /// Create another stream on the same recognizer, e.g. one per call or user.
+    ///
+    /// The model is shared, each stream has its own audio, state and hotwords.
+    /// Without `hotwords` the stream uses the hotwords file of the recognizer, if any.
+    pub fn new_session(&self, hotwords: Option<&[Hotword]>) -> Result<Self> {
+        let stream = match hotwords {
+            Some(hotwords) => self.create_stream_with_hotwords(hotwords)?,
+            None => unsafe { SherpaOnnxCreateOnlineStream(self.recognizer.recognizer) },
+        };
+        if stream.is_null() {
+            bail!("Failed to create online stream");
+        }
+        Ok(Self {
+            recognizer: Arc::clone(&self.recognizer),
+            stream,
+        })
+    }
+
+    /// Replace the hotwords of this stream.
+    ///
+    /// A new stream is created, audio buffered in the previous one is discarded.
+    pub fn set_hotwords(&mut self, hotwords: &[Hotword]) -> Result<()> {
+        let stream = self.create_stream_with_hotwords(hotwords)?;
+        unsafe { sherpa_rs_sys::SherpaOnnxDestroyOnlineStream(self.stream) };
+        self.stream = stream;
+        Ok(())
+    }
+
+    fn create_stream_with_hotwords(
+        &self,
+        hotwords: &[Hotword],
+    ) -> Result<*mut sherpa_rs_sys::SherpaOnnxOnlineStream> {
+        if !self.recognizer.supports_hotwords {
+            bail!("Per-stream hotwords need a transducer model with Search::Beam");
+        }
+        let lines = hotwords
+            .iter()
+            .map(Hotword::to_line)
+            .collect::<Result<Vec<_>>>()?;
+        let hotwords_c = CString::new(lines.join("/"))?;
+        let stream = unsafe {
+            SherpaOnnxCreateOnlineStreamWithHotwords(
+                self.recognizer.recognizer,
+                hotwords_c.as_ptr(),
+            )
+        };
+        if stream.is_null() {
+            bail!("Failed to create online stream with hotwords");
+        }
+        Ok(stream)
+    }
+}
+
+impl RecognizerStream {
+    /// Text of the current segment together with its tokens and their times.
+    pub fn result(&mut self) -> OnlineResult {
+        unsafe {
+            let result = sherpa_rs_sys::SherpaOnnxGetOnlineStreamResult(
+                self.recognizer.recognizer,
+                self.stream,
+            );
+            let raw_result = result.read();
+            let text = CStr::from_ptr(raw_result.text);
+            let text = text.to_str().unwrap().to_string();
+            let (tokens, timestamps) = read_tokens(
+                raw_result.tokens_arr,
+                raw_result.timestamps,
+                raw_result.count,
+            );
+            // Free
+            sherpa_rs_sys::SherpaOnnxDestroyOnlineRecognizerResult(result);
+            OnlineResult {
+                text,
+                tokens,
+                timestamps,
+            }
+        }
     }
 }
 
@@ -202,7 +416,6 @@ impl Drop for RecognizerStream {
     fn drop(&mut self) {
         unsafe {
             sherpa_rs_sys::SherpaOnnxDestroyOnlineStream(self.stream);
-            sherpa_rs_sys::SherpaOnnxDestroyOnlineRecognizer(self.recognizer);
         }
     }
 }
@@ -221,37 +434,72 @@ impl OnlineStream for RecognizerStream {
 
     fn decode_stream(&mut self) {
         unsafe {
-            sherpa_rs_sys::SherpaOnnxDecodeOnlineStream(self.recognizer, self.stream);
+            sherpa_rs_sys::SherpaOnnxDecodeOnlineStream(self.recognizer.recognizer, self.stream);
         }
     }
 
     fn is_ready(&mut self) -> bool {
-        unsafe { sherpa_rs_sys::SherpaOnnxIsOnlineStreamReady(self.recognizer, self.stream) == 1 }
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxIsOnlineStreamReady(self.recognizer.recognizer, self.stream)
+                == 1
+        }
     }
 
     fn get_result(&mut self) -> String {
-        unsafe {
-            let result =
-                sherpa_rs_sys::SherpaOnnxGetOnlineStreamResult(self.recognizer, self.stream);
-            let raw_result = result.read();
-            let text = CStr::from_ptr(raw_result.text);
-            let text = text.to_str().unwrap().to_string();
-            // let timestamps: &[f32] =
-            // std::slice::from_raw_parts(raw_result.timestamps, raw_result.count as usize);
-            let str = text;
-            // Free
-            sherpa_rs_sys::SherpaOnnxDestroyOnlineRecognizerResult(result);
-            str
-        }
+        self.result().text
     }
 
     fn is_endpoint(&mut self) -> bool {
-        unsafe { SherpaOnnxOnlineStreamIsEndpoint(self.recognizer, self.stream) == 1 }
+        unsafe { SherpaOnnxOnlineStreamIsEndpoint(self.recognizer.recognizer, self.stream) == 1 }
     }
 
     fn reset(&mut self) {
         unsafe {
-            SherpaOnnxOnlineStreamReset(self.recognizer, self.stream);
+            SherpaOnnxOnlineStreamReset(self.recognizer.recognizer, self.stream);
+        }
+    }
+
+    fn input_finished(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxOnlineStreamInputFinished(self.stream);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hotword_line() {
+        assert_eq!(Hotword::new(" 小  爱 ").to_line().unwrap(), "小 爱");
+        assert_eq!(
+            Hotword::new("▁HE LLO").with_score(2.5).to_line().unwrap(),
+            "▁HE LLO :2.5"
+        );
+        assert!(Hotword::new("  ").to_line().is_err());
+        assert!(Hotword::new("a/b").to_line().is_err());
+        assert!(Hotword::new("a:b").to_line().is_err());
+    }
+
+    #[test]
+    fn test_ctc_fst_config() {
+        assert_eq!(
+            CtcFstConfig::default().graph_and_max_active().unwrap(),
+            None
+        );
+        let without_graph = CtcFstConfig {
+            graph: None,
+            max_active: Some(100),
+        };
+        assert!(without_graph.graph_and_max_active().is_err());
+        assert!(CtcFstConfig::new("missing/HLG.fst")
+            .graph_and_max_active()
+            .is_err());
+
+        let graph = Path::new(file!());
+        let fst = CtcFstConfig::new(graph);
+        assert_eq!(fst.graph_and_max_active().unwrap(), Some((graph, 3000)));
+        assert!(fst.with_max_active(0).graph_and_max_active().is_err());
+    }
+}
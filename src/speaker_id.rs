@@ -1,5 +1,11 @@
 use eyre::{bail, Result};
-use std::{ffi::CString, path::PathBuf};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use crate::get_default_provider;
 
@@ -16,6 +22,49 @@ pub struct ExtractorConfig {
 pub struct EmbeddingExtractor {
     pub(crate) extractor: *const sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractor,
     pub embedding_size: usize,
+    model: PathBuf,
+}
+
+/// Identifies the model that produced a set of embeddings, since embeddings of
+/// different models can't be compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelId {
+    /// File name of the model, for messages only.
+    pub name: String,
+    pub size: u64,
+    /// 64-bit FNV-1a hash of the model file.
+    pub hash: u64,
+}
+
+impl ModelId {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut file = File::open(path)?;
+        let mut buffer = vec![0u8; 1 << 16];
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut size = 0;
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            for &byte in &buffer[..read] {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+            size += read as u64;
+        }
+        Ok(Self { name, size, hash })
+    }
+
+    /// Whether both are the same model file, wherever it is stored.
+    pub fn same_model(&self, other: &ModelId) -> bool {
+        self.size == other.size && self.hash == other.hash
+    }
 }
 
 impl ExtractorConfig {
@@ -60,62 +109,152 @@ impl EmbeddingExtractor {
         Ok(Self {
             extractor,
             embedding_size,
+            model: model_path,
         })
     }
 
+    /// Identity of the model file, reads the whole file.
+    pub fn model_id(&self) -> Result<ModelId> {
+        ModelId::from_file(&self.model)
+    }
+
     pub fn compute_speaker_embedding(
         &mut self,
         sample_rate: i32,
         samples: Vec<f32>,
     ) -> Result<Vec<f32>> {
+        self.embed(sample_rate, &samples)
+    }
+
+    /// Compute the embedding of a complete recording.
+    pub fn embed(&self, sample_rate: i32, samples: &[f32]) -> Result<Vec<f32>> {
+        let mut stream = self.create_stream(sample_rate)?;
+        stream.accept_waveform(samples);
+        stream.compute()
+    }
+
+    /// Start an embedding of audio that arrives in chunks.
+    pub fn create_stream(&self, sample_rate: i32) -> Result<EmbeddingStream<'_>> {
+        let stream = unsafe {
+            sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorCreateStream(self.extractor)
+        };
+        if stream.is_null() {
+            bail!("Failed to create SherpaOnnxOnlineStream");
+        }
+        Ok(EmbeddingStream {
+            extractor: self,
+            stream,
+            sample_rate,
+            num_samples: 0,
+            finished: false,
+        })
+    }
+
+    /// Embed many recordings on `num_workers` threads sharing this extractor.
+    ///
+    /// Results are in the order of `segments`. A segment too short to embed
+    /// fails on its own without affecting the others.
+    pub fn embed_batch<S: AsRef<[f32]> + Sync>(
+        &self,
+        sample_rate: i32,
+        segments: &[S],
+        num_workers: usize,
+    ) -> Vec<Result<Vec<f32>>> {
+        map_parallel(segments, num_workers, |segment| {
+            self.embed(sample_rate, segment.as_ref())
+        })
+    }
+
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn is_ready(&self, stream: *const sherpa_rs_sys::SherpaOnnxOnlineStream) -> bool {
         unsafe {
-            let stream =
-                sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorCreateStream(self.extractor);
-            if stream.is_null() {
-                bail!("Failed to create SherpaOnnxOnlineStream");
-            }
+            let result =
+                sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorIsReady(self.extractor, stream);
+            result != 0
+        }
+    }
+}
+
+/// Audio of one recording being collected for an embedding, see
+/// [`EmbeddingExtractor::create_stream`].
+pub struct EmbeddingStream<'a> {
+    extractor: &'a EmbeddingExtractor,
+    stream: *const sherpa_rs_sys::SherpaOnnxOnlineStream,
+    sample_rate: i32,
+    num_samples: usize,
+    finished: bool,
+}
 
+impl EmbeddingStream<'_> {
+    pub fn accept_waveform(&mut self, samples: &[f32]) {
+        if self.finished || samples.is_empty() {
+            return;
+        }
+        unsafe {
             sherpa_rs_sys::SherpaOnnxOnlineStreamAcceptWaveform(
-                stream,
-                sample_rate,
+                self.stream,
+                self.sample_rate,
                 samples.as_ptr(),
                 samples.len() as i32,
             );
-            sherpa_rs_sys::SherpaOnnxOnlineStreamInputFinished(stream);
+        }
+        self.num_samples += samples.len();
+    }
 
-            if !self.is_ready(stream) {
-                bail!("Embedding extractor is not ready");
-            }
+    /// No more audio will be added. Called by [`EmbeddingStream::compute`].
+    pub fn input_finished(&mut self) {
+        if !self.finished {
+            unsafe { sherpa_rs_sys::SherpaOnnxOnlineStreamInputFinished(self.stream) };
+            self.finished = true;
+        }
+    }
+
+    /// Whether there is enough audio for an embedding.
+    pub fn is_ready(&self) -> bool {
+        unsafe { self.extractor.is_ready(self.stream) }
+    }
+
+    /// Seconds of audio accepted so far.
+    pub fn duration(&self) -> f32 {
+        self.num_samples as f32 / self.sample_rate as f32
+    }
 
+    /// Finish the input and compute the embedding.
+    pub fn compute(mut self) -> Result<Vec<f32>> {
+        self.input_finished();
+        if !self.is_ready() {
+            bail!(
+                "Embedding extractor is not ready, {:.2}s of audio is too short",
+                self.duration()
+            );
+        }
+        let embedding_size = self.extractor.embedding_size;
+        unsafe {
             let embedding_ptr = sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorComputeEmbedding(
-                self.extractor,
-                stream,
+                self.extractor.extractor,
+                self.stream,
             );
             if embedding_ptr.is_null() {
                 bail!("Failed to compute speaker embedding");
             }
-            log::debug!("using dimensions {}", self.embedding_size);
-            let embedding = std::slice::from_raw_parts(embedding_ptr, self.embedding_size).to_vec();
-            // Free
-            sherpa_rs_sys::SherpaOnnxDestroyOnlineStream(stream);
+            log::debug!("using dimensions {}", embedding_size);
+            let embedding = std::slice::from_raw_parts(embedding_ptr, embedding_size).to_vec();
             sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorDestroyEmbedding(embedding_ptr);
             Ok(embedding)
         }
     }
+}
 
-    #[allow(clippy::missing_safety_doc)]
-    pub unsafe fn is_ready(
-        &mut self,
-        stream: *const sherpa_rs_sys::SherpaOnnxOnlineStream,
-    ) -> bool {
+impl Drop for EmbeddingStream<'_> {
+    fn drop(&mut self) {
         unsafe {
-            let result =
-                sherpa_rs_sys::SherpaOnnxSpeakerEmbeddingExtractorIsReady(self.extractor, stream);
-            result != 0
+            sherpa_rs_sys::SherpaOnnxDestroyOnlineStream(self.stream);
         }
     }
 }
 
+unsafe impl Send for EmbeddingStream<'_> {}
+
 unsafe impl Send for EmbeddingExtractor {}
 unsafe impl Sync for EmbeddingExtractor {}
 
@@ -126,3 +265,85 @@ impl Drop for EmbeddingExtractor {
         }
     }
 }
+
+/// Apply `f` to every item on `num_workers` threads, which take the next item
+/// as they finish. Results are in the order of `items`.
+fn map_parallel<S, T, F>(items: &[S], num_workers: usize, f: F) -> Vec<T>
+where
+    S: Sync,
+    T: Send,
+    F: Fn(&S) -> T + Sync,
+{
+    let num_workers = num_workers.clamp(1, items.len().max(1));
+    if num_workers == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, T)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            break;
+                        };
+                        results.push((i, f(item)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("embedding worker panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Cosine similarity of two embeddings, in `-1.0..=1.0`. Zero if either is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_parallel_keeps_order_and_errors() {
+        // Segments of varying length, like the windows of an embedding batch
+        let segments: Vec<Vec<f32>> = (0..50).map(|i| vec![0.0; i % 7]).collect();
+        let embed = |segment: &Vec<f32>| -> Result<usize> {
+            std::thread::sleep(std::time::Duration::from_micros(50 * segment.len() as u64));
+            if segment.len() < 2 {
+                bail!("{} samples is too short", segment.len())
+            }
+            Ok(segment.len())
+        };
+        for num_workers in [0, 1, 4, 100] {
+            let results = map_parallel(&segments, num_workers, embed);
+            assert_eq!(results.len(), segments.len());
+            for (i, result) in results.iter().enumerate() {
+                match result {
+                    Ok(len) => assert_eq!(*len, i % 7),
+                    Err(error) => {
+                        assert!(i % 7 < 2);
+                        assert_eq!(error.to_string(), format!("{} samples is too short", i % 7));
+                    }
+                }
+            }
+        }
+        assert!(map_parallel(&[] as &[Vec<f32>], 4, embed).is_empty());
+    }
+}
@@ -0,0 +1,437 @@
+//! Encode plain text into the model units used by keyword and hotword files.
+//!
+//! This is a port of the `text2token` tool shipped with sherpa-onnx. It reads
+//! `tokens.txt` and, depending on the modeling unit, a BPE vocab (`bpe.vocab`)
+//! or a pinyin lexicon, and turns phrases into space separated tokens.
+
+use eyre::{bail, Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use crate::online::stream::{keyword_spotter::Keyword, recognizer::Hotword};
+
+const BPE_WORD_BOUNDARY: char = '▁';
+
+/// Pinyin initials, longest first so `zh`, `ch` and `sh` win over `z`, `c` and `s`.
+const PINYIN_INITIALS: [&str; 23] = [
+    "zh", "ch", "sh", "b", "p", "m", "f", "d", "t", "n", "l", "g", "k", "h", "j", "q", "x", "r",
+    "z", "c", "s", "y", "w",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelingUnit {
+    /// One token per CJK character.
+    CjkChar,
+    /// Sentencepiece BPE pieces, requires a BPE vocab.
+    Bpe,
+    /// CJK characters, with everything else encoded as BPE pieces.
+    CjkCharBpe,
+    /// Full pinyin syllables with tones (`xiǎo`), requires a pinyin lexicon.
+    FullPinyin,
+    /// Pinyin split into initials and finals with tones (`x iǎo`), requires a pinyin lexicon.
+    PartialPinyin,
+}
+
+/// Result of encoding a phrase.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Encoding {
+    pub tokens: Vec<String>,
+    /// Characters that could not be mapped to tokens of the model, in order of appearance.
+    pub unknown: Vec<char>,
+}
+
+impl Encoding {
+    pub fn is_complete(&self) -> bool {
+        self.unknown.is_empty()
+    }
+
+    fn push_unknown(&mut self, c: char) {
+        if !self.unknown.contains(&c) {
+            self.unknown.push(c);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextEncoder {
+    unit: ModelingUnit,
+    tokens: HashSet<String>,
+    bpe_scores: HashMap<String, f32>,
+    lexicon: HashMap<String, Vec<String>>,
+    max_word_len: usize,
+}
+
+impl TextEncoder {
+    /// Create an encoder from the model's `tokens.txt`.
+    ///
+    /// `bpe_vocab` is required for [`ModelingUnit::Bpe`] and [`ModelingUnit::CjkCharBpe`].
+    /// `lexicon` is required for the pinyin units. Each line holds a word followed by
+    /// its syllables with tone marks, separated by whitespace, e.g. `小爱 xiǎo ài`.
+    /// The first entry of a word wins, and phrases are segmented into the longest
+    /// words found. Pinyin models don't ship a lexicon; generate one for the words
+    /// you need, e.g. with `pypinyin`, which the upstream tool uses.
+    pub fn new(
+        tokens: &Path,
+        unit: ModelingUnit,
+        bpe_vocab: Option<&Path>,
+        lexicon: Option<&Path>,
+    ) -> Result<Self> {
+        let tokens = read_tokens(open(tokens)?)?;
+        let bpe_scores = bpe_vocab
+            .map(|path| read_bpe_vocab(open(path)?))
+            .transpose()?;
+        let lexicon = lexicon.map(|path| read_lexicon(open(path)?)).transpose()?;
+        Self::from_parts(unit, tokens, bpe_scores, lexicon)
+    }
+
+    fn from_parts(
+        unit: ModelingUnit,
+        tokens: HashSet<String>,
+        bpe_scores: Option<HashMap<String, f32>>,
+        lexicon: Option<HashMap<String, Vec<String>>>,
+    ) -> Result<Self> {
+        let needs_bpe = matches!(unit, ModelingUnit::Bpe | ModelingUnit::CjkCharBpe);
+        let needs_lexicon = matches!(unit, ModelingUnit::FullPinyin | ModelingUnit::PartialPinyin);
+        if needs_bpe && bpe_scores.is_none() {
+            bail!("{:?} needs a BPE vocab", unit);
+        }
+        if needs_lexicon && lexicon.is_none() {
+            bail!(
+                "{:?} needs a pinyin lexicon with lines like `小爱 xiǎo ài`, see TextEncoder::new",
+                unit
+            );
+        }
+        let lexicon = lexicon.unwrap_or_default();
+        let max_word_len = lexicon.keys().map(|w| w.chars().count()).max().unwrap_or(0);
+        Ok(Self {
+            unit,
+            tokens,
+            bpe_scores: bpe_scores.unwrap_or_default(),
+            lexicon,
+            max_word_len,
+        })
+    }
+
+    pub fn modeling_unit(&self) -> ModelingUnit {
+        self.unit
+    }
+
+    /// Encode `text`, collecting characters that are not in the vocabulary.
+    pub fn encode(&self, text: &str) -> Encoding {
+        let mut encoding = Encoding::default();
+        match self.unit {
+            ModelingUnit::CjkChar => {
+                for c in text.chars().filter(|c| !c.is_whitespace()) {
+                    self.push_token(&mut encoding, c.to_string(), &[c]);
+                }
+            }
+            ModelingUnit::Bpe => {
+                for word in text.split_whitespace() {
+                    self.encode_bpe_word(&mut encoding, word);
+                }
+            }
+            ModelingUnit::CjkCharBpe => {
+                for word in text.split_whitespace() {
+                    let mut run = String::new();
+                    for c in word.chars() {
+                        if is_cjk(c) {
+                            if !run.is_empty() {
+                                self.encode_bpe_word(&mut encoding, &run);
+                                run.clear();
+                            }
+                            self.push_token(&mut encoding, c.to_string(), &[c]);
+                        } else {
+                            run.push(c);
+                        }
+                    }
+                    if !run.is_empty() {
+                        self.encode_bpe_word(&mut encoding, &run);
+                    }
+                }
+            }
+            ModelingUnit::FullPinyin | ModelingUnit::PartialPinyin => {
+                for word in text.split_whitespace() {
+                    self.encode_pinyin_word(&mut encoding, word);
+                }
+            }
+        }
+        encoding
+    }
+
+    /// Characters of `text` that cannot be encoded with this model.
+    pub fn unknown_chars(&self, text: &str) -> Vec<char> {
+        self.encode(text).unknown
+    }
+
+    /// Encode `phrase` into a [`Keyword`] for a keyword spotter.
+    ///
+    /// The phrase itself, with spaces replaced by `_`, is used as the reported keyword.
+    pub fn keyword(&self, phrase: &str) -> Result<Keyword> {
+        let tokens = self.encode_complete(phrase)?;
+        let display = phrase.split_whitespace().collect::<Vec<_>>().join("_");
+        Ok(Keyword::new(tokens.join(" ")).with_phrase(display))
+    }
+
+    /// Encode `phrase` into a [`Hotword`] for a recognizer stream.
+    pub fn hotword(&self, phrase: &str, score: Option<f32>) -> Result<Hotword> {
+        let tokens = self.encode_complete(phrase)?;
+        Ok(Hotword {
+            text: tokens.join(" "),
+            score,
+        })
+    }
+
+    /// Encode `phrase` into a hotwords file line, `tokens :score`.
+    pub fn hotword_line(&self, phrase: &str, score: Option<f32>) -> Result<String> {
+        self.hotword(phrase, score)?.to_line()
+    }
+
+    fn encode_complete(&self, phrase: &str) -> Result<Vec<String>> {
+        let encoding = self.encode(phrase);
+        if !encoding.is_complete() {
+            let unknown: String = encoding.unknown.iter().collect();
+            bail!(
+                "`{}` has characters not in the vocabulary: {}",
+                phrase,
+                unknown
+            );
+        }
+        if encoding.tokens.is_empty() {
+            bail!("`{}` encodes to no tokens", phrase);
+        }
+        Ok(encoding.tokens)
+    }
+
+    fn push_token(&self, encoding: &mut Encoding, token: String, source: &[char]) {
+        if self.tokens.contains(&token) {
+            encoding.tokens.push(token);
+        } else {
+            source.iter().for_each(|&c| encoding.push_unknown(c));
+        }
+    }
+
+    /// Sentencepiece BPE: start from characters and repeatedly merge the adjacent
+    /// pair whose concatenation has the highest score in the vocab.
+    fn encode_bpe_word(&self, encoding: &mut Encoding, word: &str) {
+        let word = format!("{}{}", BPE_WORD_BOUNDARY, word);
+        let mut pieces: Vec<String> = word.chars().map(String::from).collect();
+
+        loop {
+            let best = pieces
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| {
+                    let merged = format!("{}{}", pair[0], pair[1]);
+                    self.bpe_scores.get(&merged).map(|&score| (i, score))
+                })
+                .fold(None, |best: Option<(usize, f32)>, (i, score)| match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _ => Some((i, score)),
+                });
+            let Some((i, _)) = best else { break };
+            let right = pieces.remove(i + 1);
+            pieces[i].push_str(&right);
+        }
+
+        for piece in pieces {
+            let chars: Vec<char> = piece.chars().filter(|&c| c != BPE_WORD_BOUNDARY).collect();
+            if self.tokens.contains(&piece) {
+                encoding.tokens.push(piece);
+            } else if chars.is_empty() {
+                // A lone word boundary the model has no token for, nothing to report.
+                continue;
+            } else {
+                chars.iter().for_each(|&c| encoding.push_unknown(c));
+            }
+        }
+    }
+
+    /// Segment `word` with longest match against the lexicon.
+    fn encode_pinyin_word(&self, encoding: &mut Encoding, word: &str) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut start = 0;
+        while start < chars.len() {
+            let longest = (1..=self.max_word_len.min(chars.len() - start))
+                .rev()
+                .find_map(|len| {
+                    let candidate: String = chars[start..start + len].iter().collect();
+                    self.lexicon
+                        .get(&candidate)
+                        .map(|syllables| (len, syllables))
+                });
+            match longest {
+                Some((len, syllables)) => {
+                    let source = &chars[start..start + len];
+                    for syllable in syllables {
+                        if self.unit == ModelingUnit::PartialPinyin {
+                            for part in split_pinyin(syllable) {
+                                self.push_token(encoding, part.to_string(), source);
+                            }
+                        } else {
+                            self.push_token(encoding, syllable.clone(), source);
+                        }
+                    }
+                    start += len;
+                }
+                None => {
+                    encoding.push_unknown(chars[start]);
+                    start += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Split a pinyin syllable into its initial and final, e.g. `xiǎo` -> `x`, `iǎo`.
+/// Syllables without an initial, such as `ài`, are returned as is.
+pub fn split_pinyin(syllable: &str) -> Vec<&str> {
+    PINYIN_INITIALS
+        .iter()
+        .find_map(|initial| {
+            syllable
+                .strip_prefix(initial)
+                .filter(|rest| !rest.is_empty())
+                .map(|rest| vec![&syllable[..initial.len()], rest])
+        })
+        .unwrap_or_else(|| vec![syllable])
+}
+
+/// Whether `c` is a CJK ideograph, kana or hangul syllable, which `cjkchar`
+/// models treat as a token of its own.
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2FA1F
+        | 0x3040..=0x30FF
+        | 0xAC00..=0xD7AF)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+/// `tokens.txt` holds one `token id` pair per line.
+fn read_tokens<R: BufRead>(reader: R) -> Result<HashSet<String>> {
+    let mut tokens = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let token = match line.rsplit_once(char::is_whitespace) {
+            Some((token, id)) if id.parse::<i32>().is_ok() => token,
+            _ => bail!("invalid line in tokens file: `{}`", line),
+        };
+        tokens.insert(token.to_string());
+    }
+    Ok(tokens)
+}
+
+/// `bpe.vocab` holds one `piece<TAB>score` pair per line.
+fn read_bpe_vocab<R: BufRead>(reader: R) -> Result<HashMap<String, f32>> {
+    let mut scores = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        let (piece, score) = match line.rsplit_once(char::is_whitespace) {
+            Some((piece, score)) => (piece, score.parse::<f32>()),
+            None => bail!("invalid line in BPE vocab: `{}`", line),
+        };
+        let score = score.with_context(|| format!("invalid score in BPE vocab: `{}`", line))?;
+        scores.insert(piece.to_string(), score);
+    }
+    Ok(scores)
+}
+
+fn read_lexicon<R: BufRead>(reader: R) -> Result<HashMap<String, Vec<String>>> {
+    let mut lexicon = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        let Some(word) = fields.next() else { continue };
+        let syllables: Vec<String> = fields.map(String::from).collect();
+        if syllables.is_empty() {
+            bail!("lexicon entry `{}` has no pronunciation", word);
+        }
+        lexicon.entry(word.to_string()).or_insert(syllables);
+    }
+    Ok(lexicon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoder(
+        unit: ModelingUnit,
+        tokens: &str,
+        bpe: Option<&str>,
+        lexicon: Option<&str>,
+    ) -> TextEncoder {
+        let tokens = read_tokens(tokens.as_bytes()).unwrap();
+        let bpe = bpe.map(|v| read_bpe_vocab(v.as_bytes()).unwrap());
+        let lexicon = lexicon.map(|l| read_lexicon(l.as_bytes()).unwrap());
+        TextEncoder::from_parts(unit, tokens, bpe, lexicon).unwrap()
+    }
+
+    #[test]
+    fn test_cjkchar_reports_unknown() {
+        let encoder = encoder(ModelingUnit::CjkChar, "<blk> 0\n你 1\n好 2\n", None, None);
+        let encoding = encoder.encode("你好 世界");
+        assert_eq!(encoding.tokens, vec!["你", "好"]);
+        assert_eq!(encoding.unknown, vec!['世', '界']);
+        assert!(encoder.keyword("世界").is_err());
+    }
+
+    #[test]
+    fn test_bpe_merges_by_score() {
+        let tokens = "▁HE 0\nLL 1\nO 2\n▁WORLD 3\n";
+        let vocab = "▁ 0\nH -9\nE -9\nL -9\nO -9\nW -9\nR -9\nD -9\n▁H -1\n▁HE -0.5\nLL -2\n\
+                     ▁W -1\n▁WO -1.5\n▁WOR -1.6\n▁WORL -1.7\n▁WORLD -1.8\n";
+        let encoder = encoder(ModelingUnit::Bpe, tokens, Some(vocab), None);
+        let keyword = encoder.keyword("HELLO WORLD").unwrap();
+        assert_eq!(keyword.tokens, "▁HE LL O ▁WORLD");
+        assert_eq!(keyword.to_line().unwrap(), "▁HE LL O ▁WORLD @HELLO_WORLD");
+    }
+
+    #[test]
+    fn test_partial_pinyin() {
+        let tokens = "x 0\niǎo 1\nài 2\nt 3\nóng 4\nué 5\n";
+        let lexicon = "小 xiǎo\n爱 ài\n同学 tóng xué\n";
+        let encoder = encoder(ModelingUnit::PartialPinyin, tokens, None, Some(lexicon));
+        assert_eq!(
+            encoder.hotword_line("小爱同学", Some(2.0)).unwrap(),
+            "x iǎo ài t óng x ué :2"
+        );
+        assert_eq!(encoder.unknown_chars("小猫"), vec!['猫']);
+
+        let tokens = read_tokens(tokens.as_bytes()).unwrap();
+        let error = TextEncoder::from_parts(ModelingUnit::FullPinyin, tokens, None, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("needs a pinyin lexicon"));
+    }
+
+    #[test]
+    fn test_split_pinyin() {
+        assert_eq!(split_pinyin("zhǎng"), vec!["zh", "ǎng"]);
+        assert_eq!(split_pinyin("zǎo"), vec!["z", "ǎo"]);
+        assert_eq!(split_pinyin("chī"), vec!["ch", "ī"]);
+        assert_eq!(split_pinyin("cí"), vec!["c", "í"]);
+        assert_eq!(split_pinyin("shì"), vec!["sh", "ì"]);
+        assert_eq!(split_pinyin("sì"), vec!["s", "ì"]);
+        assert_eq!(split_pinyin("ài"), vec!["ài"]);
+        assert_eq!(split_pinyin("ēn"), vec!["ēn"]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{cstr, get_default_provider};
+use crate::{cstr, get_default_provider, read_tokens};
 use std::{
     ffi::{CStr, CString},
     ptr::null,
@@ -12,7 +12,9 @@ pub struct WhisperRecognizer {
 #[derive(Debug)]
 pub struct WhisperRecognizerResult {
     pub text: String,
-    // pub timestamps: Vec<f32>,
+    pub tokens: Vec<String>,
+    /// Start of each token in seconds, empty if the model doesn't report them.
+    pub timestamps: Vec<f32>,
 }
 
 impl WhisperRecognizer {
@@ -114,9 +116,16 @@ impl WhisperRecognizer {
             let raw_result = result_ptr.read();
             let text = CStr::from_ptr(raw_result.text);
             let text = text.to_str().unwrap().to_string();
-            // let timestamps: &[f32] =
-            // std::slice::from_raw_parts(raw_result.timestamps, raw_result.count as usize);
-            let result = WhisperRecognizerResult { text };
+            let (tokens, timestamps) = read_tokens(
+                raw_result.tokens_arr,
+                raw_result.timestamps,
+                raw_result.count,
+            );
+            let result = WhisperRecognizerResult {
+                text,
+                tokens,
+                timestamps,
+            };
             // Free
             sherpa_rs_sys::SherpaOnnxDestroyOfflineRecognizerResult(result_ptr);
             sherpa_rs_sys::SherpaOnnxDestroyOfflineStream(stream);
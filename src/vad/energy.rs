@@ -0,0 +1,281 @@
+//! Voice activity detection from frame energy and zero-crossing rate.
+//!
+//! Needs no model, which makes it useful for tests and small builds. It is far less
+//! robust than silero against noise, music or loud breathing.
+//!
+//! A frame counts as speech when its level is above the threshold and its
+//! zero-crossing rate is low enough to rule out hiss. Speech starts at a higher
+//! level than it has to keep, so a segment doesn't flicker around the threshold.
+
+use std::collections::VecDeque;
+
+use eyre::{bail, Result};
+
+use super::{SpeechSegment, VoiceActivityDetector};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyVadConfig {
+    pub sample_rate: i32,
+    /// Frame length in seconds.
+    pub frame_duration: f32,
+    /// Level in dBFS a frame needs to start speech.
+    pub start_threshold: f32,
+    /// Level in dBFS a frame needs to continue speech, at most `start_threshold`.
+    pub stop_threshold: f32,
+    /// Frames crossing zero more often than this fraction of samples are noise.
+    pub max_zero_crossing_rate: f32,
+    pub min_speech_duration: f32,
+    pub min_silence_duration: f32,
+}
+
+impl Default for EnergyVadConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            frame_duration: 0.02,
+            start_threshold: -35.0,
+            stop_threshold: -40.0,
+            max_zero_crossing_rate: 0.5,
+            min_speech_duration: 0.25,
+            min_silence_duration: 0.5,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EnergyVad {
+    config: EnergyVadConfig,
+    frame_size: usize,
+    min_speech: usize,
+    min_silence: usize,
+    /// Samples waiting for a full frame.
+    pending: Vec<f32>,
+    /// Audio of the current (possible) segment, starting at `audio_start`.
+    audio: Vec<f32>,
+    audio_start: usize,
+    /// Samples processed so far.
+    processed: usize,
+    speaking: bool,
+    /// First frame of the current run of speech frames.
+    speech_start: Option<usize>,
+    /// First frame of the current run of silent frames while speaking.
+    silence_start: Option<usize>,
+    segments: VecDeque<SpeechSegment>,
+}
+
+impl EnergyVad {
+    pub fn new(config: EnergyVadConfig) -> Result<Self> {
+        if config.sample_rate <= 0 {
+            bail!("sample rate must be positive, got {}", config.sample_rate);
+        }
+        if config.stop_threshold > config.start_threshold {
+            bail!(
+                "stop threshold {} must not be above start threshold {}",
+                config.stop_threshold,
+                config.start_threshold
+            );
+        }
+        let to_samples = |seconds: f32| (seconds.max(0.0) * config.sample_rate as f32) as usize;
+        let frame_size = to_samples(config.frame_duration);
+        if frame_size == 0 {
+            bail!("frame duration {} is too short", config.frame_duration);
+        }
+        Ok(Self {
+            frame_size,
+            min_speech: to_samples(config.min_speech_duration),
+            min_silence: to_samples(config.min_silence_duration),
+            config,
+            pending: Vec::new(),
+            audio: Vec::new(),
+            audio_start: 0,
+            processed: 0,
+            speaking: false,
+            speech_start: None,
+            silence_start: None,
+            segments: VecDeque::new(),
+        })
+    }
+
+    pub fn config(&self) -> &EnergyVadConfig {
+        &self.config
+    }
+
+    fn is_active(&self, frame: &[f32]) -> bool {
+        let threshold = if self.speaking {
+            self.config.stop_threshold
+        } else {
+            self.config.start_threshold
+        };
+        level(frame) >= threshold && zero_crossing_rate(frame) <= self.config.max_zero_crossing_rate
+    }
+
+    fn process_frame(&mut self, frame: &[f32]) {
+        let frame_start = self.processed;
+        if !self.speaking && self.speech_start.is_none() {
+            // Nothing to keep from before this frame
+            self.audio.clear();
+            self.audio_start = frame_start;
+        }
+        let active = self.is_active(frame);
+        self.audio.extend_from_slice(frame);
+        self.processed += frame.len();
+
+        if !self.speaking {
+            if !active {
+                self.speech_start = None;
+                return;
+            }
+            let start = *self.speech_start.get_or_insert(frame_start);
+            if self.processed - start >= self.min_speech {
+                self.speaking = true;
+                self.silence_start = None;
+            }
+        } else if active {
+            self.silence_start = None;
+        } else {
+            let silence_start = *self.silence_start.get_or_insert(frame_start);
+            if self.processed - silence_start >= self.min_silence {
+                self.end_segment(silence_start);
+            }
+        }
+    }
+
+    fn end_segment(&mut self, end: usize) {
+        if let Some(start) = self.speech_start {
+            let samples = self.audio[start - self.audio_start..end - self.audio_start].to_vec();
+            self.segments.push_back(SpeechSegment {
+                start: start as i32,
+                samples,
+                sample_rate: self.config.sample_rate,
+            });
+        }
+        self.speaking = false;
+        self.speech_start = None;
+        self.silence_start = None;
+    }
+}
+
+impl VoiceActivityDetector for EnergyVad {
+    fn sample_rate(&self) -> i32 {
+        self.config.sample_rate
+    }
+
+    fn window_size(&self) -> usize {
+        self.frame_size
+    }
+
+    fn accept_waveform(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+        let pending = std::mem::take(&mut self.pending);
+        let mut frames = pending.chunks_exact(self.frame_size);
+        for frame in frames.by_ref() {
+            self.process_frame(frame);
+        }
+        self.pending = frames.remainder().to_vec();
+    }
+
+    fn is_speech(&mut self) -> bool {
+        self.speaking
+    }
+
+    fn pop_segment(&mut self) -> Option<SpeechSegment> {
+        self.segments.pop_front()
+    }
+
+    fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        if !pending.is_empty() {
+            self.process_frame(&pending);
+        }
+        if self.speaking {
+            let end = self.silence_start.unwrap_or(self.processed);
+            self.end_segment(end);
+        }
+        self.speech_start = None;
+    }
+
+    fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    fn reset(&mut self) {
+        self.pending.clear();
+        self.audio.clear();
+        self.audio_start = 0;
+        self.processed = 0;
+        self.speaking = false;
+        self.speech_start = None;
+        self.silence_start = None;
+        self.segments.clear();
+    }
+}
+
+/// RMS level in dBFS.
+fn level(frame: &[f32]) -> f32 {
+    let energy: f32 = frame.iter().map(|s| s * s).sum::<f32>() / frame.len().max(1) as f32;
+    10.0 * energy.max(1e-10).log10()
+}
+
+fn zero_crossing_rate(frame: &[f32]) -> f32 {
+    if frame.len() < 2 {
+        return 0.0;
+    }
+    let crossings = frame
+        .windows(2)
+        .filter(|pair| (pair[0] >= 0.0) != (pair[1] >= 0.0))
+        .count();
+    crossings as f32 / (frame.len() - 1) as f32
+}
+
+/// A 200 Hz sine at 16 kHz with peak `amplitude`, i.e. a level of about
+/// `20 * log10(amplitude) - 3` dBFS.
+#[cfg(test)]
+pub(crate) fn tone(seconds: f32, amplitude: f32) -> Vec<f32> {
+    (0..(seconds * 16000.0) as usize)
+        .map(|i| amplitude * (2.0 * std::f32::consts::PI * 200.0 * i as f32 / 16000.0).sin())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Peak amplitudes of tones above the start threshold of -35 dBFS, between it and
+    /// the stop threshold of -40 dBFS, and below both.
+    const LOUD: f32 = 0.3;
+    const BETWEEN: f32 = 0.019;
+    const QUIET: f32 = 0.008;
+
+    fn segments(pieces: &[(f32, f32)]) -> Vec<(usize, usize)> {
+        let samples: Vec<f32> = pieces
+            .iter()
+            .flat_map(|&(seconds, amplitude)| tone(seconds, amplitude))
+            .collect();
+        let mut vad = EnergyVad::new(EnergyVadConfig::default()).unwrap();
+        let segments = vad.segment_buffer(&samples);
+        segments.iter().map(|s| (s.start, s.end)).collect()
+    }
+
+    #[test]
+    fn test_segments_with_hysteresis() {
+        assert!((level(&tone(0.02, BETWEEN)) + 37.5).abs() < 0.5);
+        assert!(level(&tone(0.02, QUIET)) < -44.0);
+
+        // A level between the thresholds doesn't start speech, but keeps it going
+        // for longer than the minimum silence
+        let ranges = segments(&[
+            (0.5, 0.0),
+            (0.5, BETWEEN),
+            (1.0, LOUD),
+            (1.0, BETWEEN),
+            (0.5, LOUD),
+            (1.0, 0.0),
+        ]);
+        assert_eq!(ranges, vec![(16000, 56000)]);
+
+        // Below the stop threshold speech ends. The second tone runs until the end
+        // and is closed by flush.
+        let ranges = segments(&[(0.5, 0.0), (1.0, LOUD), (1.0, QUIET), (0.5, LOUD)]);
+        assert_eq!(ranges, vec![(8000, 24000), (40000, 48000)]);
+    }
+}
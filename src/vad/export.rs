@@ -0,0 +1,157 @@
+//! Write speech segments to audio files, either one clip per segment with a
+//! manifest of offsets or a single silence-stripped recording.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use hound::{WavSpec, WavWriter};
+
+use super::{TimedSegment, VoiceActivityDetector};
+
+/// Name of the manifest written by [`export_segments`].
+pub const MANIFEST_FILE: &str = "segments.tsv";
+
+/// A segment written by [`export_segments`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedSegment {
+    pub path: PathBuf,
+    pub start: usize,
+    pub end: usize,
+    pub start_sec: f32,
+    pub end_sec: f32,
+}
+
+/// Write `samples` as a mono 16-bit wav file.
+pub fn write_wav<P: AsRef<Path>>(path: P, samples: &[f32], sample_rate: i32) -> Result<()> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: sample_rate as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    for &sample in samples {
+        writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Write each segment to `dir/{prefix}_{index}.wav` and list them in
+/// `dir/segments.tsv` together with their offsets in samples and seconds.
+///
+/// `dir` is created if needed. Paths in the manifest are relative to `dir`.
+pub fn export_segments<P: AsRef<Path>>(
+    dir: P,
+    prefix: &str,
+    sample_rate: i32,
+    segments: &[TimedSegment],
+) -> Result<Vec<ExportedSegment>> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+
+    let mut manifest = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    writeln!(manifest, "file\tstart\tend\tstart_sec\tend_sec")?;
+
+    let mut exported = Vec::with_capacity(segments.len());
+    for (index, segment) in segments.iter().enumerate() {
+        let file_name = format!("{}_{:04}.wav", prefix, index);
+        let path = dir.join(&file_name);
+        write_wav(&path, &segment.samples, sample_rate)?;
+        writeln!(
+            manifest,
+            "{}\t{}\t{}\t{:.3}\t{:.3}",
+            file_name, segment.start, segment.end, segment.start_sec, segment.end_sec
+        )?;
+        exported.push(ExportedSegment {
+            path,
+            start: segment.start,
+            end: segment.end,
+            start_sec: segment.start_sec,
+            end_sec: segment.end_sec,
+        });
+    }
+    manifest.flush()?;
+    Ok(exported)
+}
+
+/// Join the segments into one buffer, overlapping neighbours by `crossfade`
+/// seconds with a linear fade. A crossfade of 0 simply appends them.
+pub fn concatenate(segments: &[TimedSegment], sample_rate: i32, crossfade: f32) -> Vec<f32> {
+    let fade_len = (crossfade.max(0.0) * sample_rate as f32) as usize;
+    let mut output: Vec<f32> = Vec::with_capacity(segments.iter().map(|s| s.samples.len()).sum());
+    for segment in segments {
+        let fade = fade_len.min(output.len()).min(segment.samples.len());
+        let offset = output.len() - fade;
+        for (i, &sample) in segment.samples[..fade].iter().enumerate() {
+            let gain = (i + 1) as f32 / (fade + 1) as f32;
+            let out = &mut output[offset + i];
+            *out = *out * (1.0 - gain) + sample * gain;
+        }
+        output.extend_from_slice(&segment.samples[fade..]);
+    }
+    output
+}
+
+/// Detect speech in a complete recording and return it with the silence removed.
+pub fn remove_silence<V: VoiceActivityDetector>(
+    vad: &mut V,
+    samples: &[f32],
+    crossfade: f32,
+) -> Vec<f32> {
+    let segments = vad.segment_buffer(samples);
+    concatenate(&segments, vad.sample_rate(), crossfade)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concatenate_with_crossfade() {
+        let first = TimedSegment::from_buffer(&[1.0; 4], 10, 0, 4);
+        let second = TimedSegment::from_buffer(&[0.0; 4], 10, 0, 4);
+        let joined = concatenate(&[first, second], 10, 0.2);
+        assert_eq!(joined.len(), 6);
+        assert_eq!(&joined[..2], &[1.0, 1.0]);
+        assert!((joined[2] - 2.0 / 3.0).abs() < 1e-6);
+        assert!((joined[3] - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(&joined[4..], &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_export_segments() {
+        let dir = std::env::temp_dir().join(format!("sherpa-rs-export-{}", std::process::id()));
+        let buffer: Vec<f32> = (0..40).map(|i| i as f32 / 40.0).collect();
+        let segments = [
+            TimedSegment::from_buffer(&buffer, 10, 5, 15),
+            TimedSegment::from_buffer(&buffer, 10, 25, 40),
+        ];
+        let exported = export_segments(&dir, "clip", 10, &segments).unwrap();
+
+        let paths: Vec<PathBuf> = exported.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![dir.join("clip_0000.wav"), dir.join("clip_0001.wav")]
+        );
+        let lengths: Vec<u32> = paths
+            .iter()
+            .map(|path| hound::WavReader::open(path).unwrap().len())
+            .collect();
+        assert_eq!(lengths, vec![10, 15]);
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(
+            manifest.lines().collect::<Vec<_>>(),
+            vec![
+                "file\tstart\tend\tstart_sec\tend_sec",
+                "clip_0000.wav\t5\t15\t0.500\t1.500",
+                "clip_0001.wav\t25\t40\t2.500\t4.000",
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,609 @@
+pub mod energy;
+pub mod export;
+pub mod postprocess;
+
+use crate::get_default_provider;
+use eyre::{bail, Result};
+use std::{ffi::CString, path::PathBuf};
+
+#[derive(Debug)]
+pub struct VadConfig {
+    pub(crate) cfg: sherpa_rs_sys::SherpaOnnxVadModelConfig,
+}
+
+/// Builder for a [`VadConfig`], see [`VadConfig::builder`].
+#[derive(Debug, Clone)]
+pub struct VadConfigBuilder {
+    model: PathBuf,
+    min_silence_duration: f32,
+    min_speech_duration: f32,
+    threshold: f32,
+    sample_rate: i32,
+    window_size: Option<i32>,
+    provider: Option<String>,
+    num_threads: Option<i32>,
+    debug: bool,
+}
+
+#[derive(Debug)]
+pub struct Vad {
+    pub(crate) vad: *mut sherpa_rs_sys::SherpaOnnxVoiceActivityDetector,
+    sample_rate: i32,
+    windows: Windows,
+}
+
+/// Splits audio of any length into windows of a fixed size, keeping the
+/// remainder until more audio arrives.
+#[derive(Debug)]
+struct Windows {
+    size: usize,
+    /// Samples waiting for a full window.
+    pending: Vec<f32>,
+}
+
+impl Windows {
+    fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Call `accept` with every window completed by `samples`.
+    fn push(&mut self, samples: &[f32], mut accept: impl FnMut(&[f32])) {
+        let mut samples = samples;
+        if !self.pending.is_empty() {
+            let take = (self.size - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() < self.size {
+                return;
+            }
+            accept(&self.pending);
+            self.pending.clear();
+        }
+
+        let mut windows = samples.chunks_exact(self.size);
+        for window in windows.by_ref() {
+            accept(window);
+        }
+        self.pending.extend_from_slice(windows.remainder());
+    }
+
+    /// The remainder padded with silence to a full window, if there is any.
+    fn take_padded(&mut self) -> Option<Vec<f32>> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let mut window = std::mem::take(&mut self.pending);
+        window.resize(self.size, 0.0);
+        Some(window)
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+impl VadConfig {
+    pub fn new(
+        model: String,
+        min_silence_duration: f32,
+        min_speech_duration: f32,
+        threshold: f32,
+        sample_rate: i32,
+        window_size: i32,
+        provider: Option<&str>,
+        num_threads: Option<i32>,
+        debug: Option<bool>,
+    ) -> Self {
+        let provider = provider.unwrap_or(get_default_provider());
+        let provider = CString::new(provider).unwrap();
+        let model = CString::new(model).unwrap();
+
+        let silero_vad = sherpa_rs_sys::SherpaOnnxSileroVadModelConfig {
+            model: model.into_raw(),
+            min_silence_duration,
+            min_speech_duration,
+            threshold,
+            window_size,
+        };
+        let debug = debug.unwrap_or(false);
+        let debug = if debug { 1 } else { 0 };
+        let cfg = sherpa_rs_sys::SherpaOnnxVadModelConfig {
+            debug,
+            provider: provider.into_raw(),
+            num_threads: num_threads.unwrap_or(1),
+            sample_rate,
+            silero_vad,
+        };
+        Self { cfg }
+    }
+
+    /// Start building a config for the silero model at `model`.
+    ///
+    /// Defaults to 16 kHz with a 512 sample window, a threshold of 0.5, 0.5 seconds
+    /// of minimum silence and 0.25 seconds of minimum speech.
+    pub fn builder<P: Into<PathBuf>>(model: P) -> VadConfigBuilder {
+        VadConfigBuilder {
+            model: model.into(),
+            min_silence_duration: 0.5,
+            min_speech_duration: 0.25,
+            threshold: 0.5,
+            sample_rate: 16000,
+            window_size: None,
+            provider: None,
+            num_threads: None,
+            debug: false,
+        }
+    }
+
+    pub fn sample_rate(&self) -> i32 {
+        self.cfg.sample_rate
+    }
+
+    pub fn window_size(&self) -> i32 {
+        self.cfg.silero_vad.window_size
+    }
+
+    pub fn as_ptr(&self) -> *const sherpa_rs_sys::SherpaOnnxVadModelConfig {
+        &self.cfg
+    }
+}
+
+impl VadConfigBuilder {
+    pub fn with_min_silence_duration(mut self, seconds: f32) -> Self {
+        self.min_silence_duration = seconds;
+        self
+    }
+
+    pub fn with_min_speech_duration(mut self, seconds: f32) -> Self {
+        self.min_speech_duration = seconds;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Either 8000 or 16000.
+    pub fn with_sample_rate(mut self, sample_rate: i32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Must be 512 at 16 kHz and 256 at 8 kHz, the default.
+    pub fn with_window_size(mut self, window_size: i32) -> Self {
+        self.window_size = Some(window_size);
+        self
+    }
+
+    pub fn with_provider<T: Into<String>>(mut self, provider: T) -> Self {
+        self.provider = Some(provider.into());
+        self
+    }
+
+    pub fn with_num_threads(mut self, num_threads: i32) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn build(self) -> Result<VadConfig> {
+        // Silero v5 takes exactly 32ms windows, other sizes silently degrade detection
+        let supported = match self.sample_rate {
+            16000 => 512,
+            8000 => 256,
+            rate => bail!("unsupported VAD sample rate {}, use 8000 or 16000", rate),
+        };
+        let window_size = self.window_size.unwrap_or(supported);
+        if window_size != supported {
+            bail!(
+                "unsupported VAD window size {} at {} Hz, use {}",
+                window_size,
+                self.sample_rate,
+                supported
+            );
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            bail!(
+                "VAD threshold must be between 0 and 1, got {}",
+                self.threshold
+            );
+        }
+        if self.min_silence_duration < 0.0 || self.min_speech_duration < 0.0 {
+            bail!("VAD durations must not be negative");
+        }
+        if !self.model.is_file() {
+            bail!("VAD model not found at {}", self.model.display());
+        }
+        let Some(model) = self.model.to_str() else {
+            bail!(
+                "VAD model path is not valid UTF-8: {}",
+                self.model.display()
+            );
+        };
+
+        Ok(VadConfig::new(
+            model.to_string(),
+            self.min_silence_duration,
+            self.min_speech_duration,
+            self.threshold,
+            self.sample_rate,
+            window_size,
+            self.provider.as_deref(),
+            self.num_threads,
+            Some(self.debug),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct SpeechSegment {
+    pub start: i32,
+    pub samples: Vec<f32>,
+    /// Sample rate of the detector that produced the segment.
+    pub sample_rate: i32,
+}
+
+impl SpeechSegment {
+    pub fn start_sec(&self) -> f32 {
+        self.start as f32 / self.sample_rate as f32
+    }
+
+    pub fn end_sec(&self) -> f32 {
+        self.start_sec() + self.duration_sec()
+    }
+
+    pub fn duration_sec(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+}
+
+/// A speech segment of a complete buffer, see [`Vad::segment_buffer`].
+///
+/// `start` and `end` are sample offsets into the buffer, `end` is exclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedSegment {
+    pub start: usize,
+    pub end: usize,
+    pub start_sec: f32,
+    pub end_sec: f32,
+    pub samples: Vec<f32>,
+}
+
+impl TimedSegment {
+    /// Cut `start..end` out of `buffer`.
+    pub(crate) fn from_buffer(buffer: &[f32], sample_rate: i32, start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            start_sec: start as f32 / sample_rate as f32,
+            end_sec: end as f32 / sample_rate as f32,
+            samples: buffer[start..end].to_vec(),
+        }
+    }
+}
+
+impl From<SpeechSegment> for TimedSegment {
+    fn from(segment: SpeechSegment) -> Self {
+        let start = segment.start.max(0) as usize;
+        Self {
+            start,
+            end: start + segment.samples.len(),
+            start_sec: segment.start_sec(),
+            end_sec: segment.end_sec(),
+            samples: segment.samples,
+        }
+    }
+}
+
+impl Vad {
+    pub fn new_from_config(config: VadConfig, buffer_size_in_seconds: f32) -> Result<Self> {
+        unsafe {
+            let vad = sherpa_rs_sys::SherpaOnnxCreateVoiceActivityDetector(
+                config.as_ptr(),
+                buffer_size_in_seconds,
+            );
+            Ok(Self {
+                vad,
+                sample_rate: config.cfg.sample_rate,
+                windows: Windows::new(config.cfg.silero_vad.window_size.max(0) as usize),
+            })
+        }
+    }
+
+    /// Number of samples the model expects per call to [`Vad::accept_waveform`].
+    pub fn window_size(&self) -> usize {
+        self.windows.size
+    }
+
+    pub fn sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        unsafe { sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorEmpty(self.vad) == 1 }
+    }
+
+    pub fn front(&mut self) -> SpeechSegment {
+        unsafe {
+            let segment_ptr = sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorFront(self.vad);
+            let raw_segment = segment_ptr.read();
+            let samples: &[f32] =
+                std::slice::from_raw_parts(raw_segment.samples, raw_segment.n as usize);
+
+            let segment = SpeechSegment {
+                samples: samples.to_vec(),
+                start: raw_segment.start,
+                sample_rate: self.sample_rate,
+            };
+
+            // Free
+            sherpa_rs_sys::SherpaOnnxDestroySpeechSegment(segment_ptr);
+
+            segment
+        }
+    }
+
+    /// Process the buffered tail and close the segment in progress.
+    ///
+    /// A partial window is padded with silence to the window size.
+    pub fn flush(&mut self) {
+        if let Some(window) = self.windows.take_padded() {
+            accept_window(self.vad, &window);
+        }
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorFlush(self.vad);
+        }
+    }
+
+    /// Feed audio of any length. It is split into windows of [`Vad::window_size`]
+    /// samples, the remainder is kept until more audio arrives or [`Vad::flush`] is called.
+    pub fn accept_waveform(&mut self, samples: &[f32]) {
+        let vad = self.vad;
+        self.windows
+            .push(samples, |window| accept_window(vad, window));
+    }
+
+    /// See [`VoiceActivityDetector::drain`].
+    pub fn drain(&mut self) -> Drain<'_, Self> {
+        Drain { vad: self }
+    }
+
+    pub fn pop(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorPop(self.vad);
+        }
+    }
+
+    pub fn is_speech(&mut self) -> bool {
+        unsafe { sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorDetected(self.vad) == 1 }
+    }
+
+    pub fn clear(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorClear(self.vad);
+        }
+    }
+
+    /// See [`VoiceActivityDetector::reset`].
+    pub fn reset(&mut self) {
+        self.windows.clear();
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorReset(self.vad);
+        }
+    }
+
+    /// See [`VoiceActivityDetector::segment_buffer`].
+    pub fn segment_buffer(&mut self, samples: &[f32]) -> Vec<TimedSegment> {
+        VoiceActivityDetector::segment_buffer(self, samples)
+    }
+}
+
+/// Common interface of the voice activity detectors, so pipelines can run on
+/// the silero [`Vad`] or on the model-free [`energy::EnergyVad`].
+pub trait VoiceActivityDetector {
+    fn sample_rate(&self) -> i32;
+
+    /// Number of samples the detector decides on at once.
+    fn window_size(&self) -> usize;
+
+    /// Feed audio of any length.
+    fn accept_waveform(&mut self, samples: &[f32]);
+
+    /// Whether the detector is currently inside speech.
+    fn is_speech(&mut self) -> bool;
+
+    /// Remove and return the oldest completed segment.
+    fn pop_segment(&mut self) -> Option<SpeechSegment>;
+
+    /// Process buffered audio and close the segment in progress.
+    fn flush(&mut self);
+
+    /// Drop the completed segments.
+    fn clear(&mut self);
+
+    /// Drop all state so the next sample is treated as the start of a new stream.
+    fn reset(&mut self);
+
+    /// Iterate over the completed speech segments, removing them from the detector.
+    fn drain(&mut self) -> Drain<'_, Self>
+    where
+        Self: Sized,
+    {
+        Drain { vad: self }
+    }
+
+    /// Find every speech segment of a complete recording, including one that
+    /// runs until the end of it.
+    ///
+    /// The detector is reset before and after, so it may be reused for another buffer.
+    fn segment_buffer(&mut self, samples: &[f32]) -> Vec<TimedSegment> {
+        let sample_rate = self.sample_rate();
+        self.reset();
+        let mut segments = Vec::new();
+        for chunk in samples.chunks(sample_rate.max(1) as usize) {
+            self.accept_waveform(chunk);
+            segments.extend(std::iter::from_fn(|| self.pop_segment()));
+        }
+        self.flush();
+        segments.extend(std::iter::from_fn(|| self.pop_segment()));
+        self.reset();
+
+        segments
+            .into_iter()
+            .filter_map(|segment| {
+                // The last window may be padded by flush, don't go past the input
+                let start = (segment.start.max(0) as usize).min(samples.len());
+                let end = (start + segment.samples.len()).min(samples.len());
+                if start == end {
+                    return None;
+                }
+                Some(TimedSegment::from_buffer(samples, sample_rate, start, end))
+            })
+            .collect()
+    }
+}
+
+impl VoiceActivityDetector for Vad {
+    fn sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    fn window_size(&self) -> usize {
+        self.windows.size
+    }
+
+    fn accept_waveform(&mut self, samples: &[f32]) {
+        Vad::accept_waveform(self, samples)
+    }
+
+    fn is_speech(&mut self) -> bool {
+        Vad::is_speech(self)
+    }
+
+    fn pop_segment(&mut self) -> Option<SpeechSegment> {
+        if self.is_empty() {
+            return None;
+        }
+        let segment = self.front();
+        self.pop();
+        Some(segment)
+    }
+
+    fn flush(&mut self) {
+        Vad::flush(self)
+    }
+
+    fn clear(&mut self) {
+        Vad::clear(self)
+    }
+
+    fn reset(&mut self) {
+        Vad::reset(self)
+    }
+}
+
+fn accept_window(vad: *mut sherpa_rs_sys::SherpaOnnxVoiceActivityDetector, window: &[f32]) {
+    unsafe {
+        sherpa_rs_sys::SherpaOnnxVoiceActivityDetectorAcceptWaveform(
+            vad,
+            window.as_ptr(),
+            window.len().try_into().unwrap(),
+        );
+    };
+}
+
+/// Iterator returned by [`VoiceActivityDetector::drain`].
+pub struct Drain<'a, V: VoiceActivityDetector> {
+    vad: &'a mut V,
+}
+
+impl<V: VoiceActivityDetector> Iterator for Drain<'_, V> {
+    type Item = SpeechSegment;
+
+    fn next(&mut self) -> Option<SpeechSegment> {
+        self.vad.pop_segment()
+    }
+}
+
+unsafe impl Send for Vad {}
+unsafe impl Sync for Vad {}
+
+impl Drop for Vad {
+    fn drop(&mut self) {
+        unsafe {
+            sherpa_rs_sys::SherpaOnnxDestroyVoiceActivityDetector(self.vad);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Windows produced from `chunks`, then the padded remainder.
+    fn windows(size: usize, chunks: &[usize]) -> (Vec<Vec<f32>>, Option<Vec<f32>>) {
+        let mut windows = Windows::new(size);
+        let mut accepted = Vec::new();
+        let mut next = 0.0;
+        for &len in chunks {
+            let chunk: Vec<f32> = (0..len).map(|i| next + i as f32).collect();
+            next += len as f32;
+            windows.push(&chunk, |window| accepted.push(window.to_vec()));
+        }
+        (accepted, windows.take_padded())
+    }
+
+    #[test]
+    fn test_windows() {
+        let counting = |from: usize, to: usize| (from..to).map(|i| i as f32).collect::<Vec<_>>();
+
+        // Smaller chunks fill a window across calls
+        let (accepted, rest) = windows(4, &[1, 2, 2, 1, 3]);
+        assert_eq!(accepted, vec![counting(0, 4), counting(4, 8)]);
+        assert_eq!(rest, Some(vec![8.0, 0.0, 0.0, 0.0]));
+
+        let (accepted, rest) = windows(4, &[4, 4]);
+        assert_eq!(accepted, vec![counting(0, 4), counting(4, 8)]);
+        assert_eq!(rest, None);
+
+        // Larger chunks, also after a partial window
+        let (accepted, rest) = windows(4, &[10, 3]);
+        assert_eq!(
+            accepted,
+            vec![counting(0, 4), counting(4, 8), counting(8, 12)]
+        );
+        assert_eq!(rest, Some(vec![12.0, 0.0, 0.0, 0.0]));
+        let (accepted, rest) = windows(4, &[1, 9, 0]);
+        assert_eq!(accepted, vec![counting(0, 4), counting(4, 8)]);
+        assert_eq!(rest, Some(vec![8.0, 9.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_config() {
+        let error = |builder: VadConfigBuilder| builder.build().unwrap_err().to_string();
+        let builder = VadConfig::builder("missing/silero_vad.onnx");
+
+        assert!(error(builder.clone().with_sample_rate(44100)).contains("sample rate 44100"));
+        assert!(error(builder.clone().with_window_size(256)).contains("window size 256"));
+        let at_8k = builder.clone().with_sample_rate(8000);
+        assert!(error(at_8k.clone().with_window_size(512)).contains("window size 512"));
+        assert!(error(builder.clone().with_threshold(1.5)).contains("threshold"));
+        assert!(error(builder.clone().with_threshold(-0.1)).contains("threshold"));
+        let negative = builder.clone().with_min_silence_duration(-0.5);
+        assert!(error(negative).contains("durations"));
+        let negative = builder.clone().with_min_speech_duration(-0.5);
+        assert!(error(negative).contains("durations"));
+
+        // Valid settings get as far as looking for the model
+        assert!(error(builder).contains("model not found"));
+        assert!(error(at_8k.with_window_size(256)).contains("model not found"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Reshape VAD segments for downstream models such as ASR.
+//!
+//! Segments are widened by a pre-pad and post-pad, segments separated by less than
+//! a gap are merged, and segments longer than a maximum duration are split at the
+//! quietest point.
+
+use super::TimedSegment;
+
+/// Length of the frames compared when looking for a split point, in seconds.
+const FRAME_DURATION: f32 = 0.01;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessor {
+    /// Seconds of audio added before each segment.
+    pub pre_pad: f32,
+    /// Seconds of audio added after each segment.
+    pub post_pad: f32,
+    /// Segments whose padded ranges are closer than this many seconds are merged.
+    pub merge_gap: f32,
+    /// Segments longer than this many seconds are split. Durations under 2 samples
+    /// are treated as 2 samples.
+    pub max_duration: Option<f32>,
+}
+
+impl Default for PostProcessor {
+    fn default() -> Self {
+        Self {
+            pre_pad: 0.0,
+            post_pad: 0.0,
+            merge_gap: 0.0,
+            max_duration: None,
+        }
+    }
+}
+
+impl PostProcessor {
+    pub fn new(pre_pad: f32, post_pad: f32, merge_gap: f32, max_duration: Option<f32>) -> Self {
+        Self {
+            pre_pad,
+            post_pad,
+            merge_gap,
+            max_duration,
+        }
+    }
+
+    /// Apply padding, merging and splitting to `segments` found in `buffer`.
+    ///
+    /// The returned segments are sorted, don't overlap and their samples are cut
+    /// from `buffer` again.
+    pub fn process(
+        &self,
+        buffer: &[f32],
+        sample_rate: i32,
+        segments: &[TimedSegment],
+    ) -> Vec<TimedSegment> {
+        let ranges = segments.iter().map(|s| (s.start, s.end)).collect();
+        self.process_ranges(buffer, sample_rate, ranges)
+            .into_iter()
+            .map(|(start, end)| TimedSegment::from_buffer(buffer, sample_rate, start, end))
+            .collect()
+    }
+
+    /// Same as [`PostProcessor::process`] for `(start, end)` sample ranges.
+    pub fn process_ranges(
+        &self,
+        buffer: &[f32],
+        sample_rate: i32,
+        mut ranges: Vec<(usize, usize)>,
+    ) -> Vec<(usize, usize)> {
+        let to_samples = |seconds: f32| (seconds.max(0.0) * sample_rate as f32) as usize;
+        let pre_pad = to_samples(self.pre_pad);
+        let post_pad = to_samples(self.post_pad);
+        let merge_gap = to_samples(self.merge_gap);
+
+        ranges.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            let start = start.saturating_sub(pre_pad).min(buffer.len());
+            let end = (end + post_pad).min(buffer.len());
+            if start >= end {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(last) if start <= last.1 + merge_gap => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        // Every split has to advance past `start`, which takes at least 2 samples
+        let max_len = match self.max_duration {
+            Some(max_duration) => to_samples(max_duration).max(2),
+            None => return merged,
+        };
+        let frame_len = to_samples(FRAME_DURATION).max(1);
+        let mut result = Vec::with_capacity(merged.len());
+        for (mut start, end) in merged {
+            while end - start > max_len {
+                let split = quietest_point(buffer, start + max_len / 2, start + max_len, frame_len);
+                result.push((start, split));
+                start = split;
+            }
+            result.push((start, end));
+        }
+        result
+    }
+}
+
+/// Middle of the lowest-energy frame in `from..to`. Ties go to the latest frame
+/// so the pieces stay as long as possible.
+fn quietest_point(buffer: &[f32], from: usize, to: usize, frame_len: usize) -> usize {
+    let frame_len = frame_len.min(to - from).max(1);
+    let mut best = (f32::INFINITY, to);
+    let mut frame_start = from;
+    while frame_start + frame_len <= to {
+        let energy: f32 = buffer[frame_start..frame_start + frame_len]
+            .iter()
+            .map(|s| s * s)
+            .sum();
+        if energy <= best.0 {
+            best = (energy, frame_start + frame_len / 2);
+        }
+        frame_start += frame_len;
+    }
+    best.1.clamp(from.max(1), to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_and_merge() {
+        let buffer = vec![0.5; 1000];
+        let post = PostProcessor::new(0.01, 0.01, 0.02, None);
+        let ranges = post.process_ranges(&buffer, 1000, vec![(500, 600), (5, 100), (130, 200)]);
+        // 5..100 and 130..200 are 10 samples apart once padded, 500..600 stays
+        assert_eq!(ranges, vec![(0, 210), (490, 610)]);
+    }
+
+    #[test]
+    fn test_split_at_quietest_point() {
+        let mut buffer = vec![0.5; 1000];
+        for sample in &mut buffer[700..710] {
+            *sample = 0.0;
+        }
+        let post = PostProcessor::new(0.0, 0.0, 0.0, Some(0.8));
+        let ranges = post.process_ranges(&buffer, 1000, vec![(0, 1000)]);
+        assert_eq!(ranges, vec![(0, 705), (705, 1000)]);
+    }
+
+    #[test]
+    fn test_split_tiny_max_duration() {
+        let post = PostProcessor::new(0.0, 0.0, 0.0, Some(0.0));
+        let ranges = post.process_ranges(&[0.5; 100], 1000, vec![(10, 50)]);
+        // Used to loop forever on empty pieces at the start of the range
+        assert!(ranges
+            .iter()
+            .all(|&(start, end)| start < end && end - start <= 2));
+        assert!(ranges.windows(2).all(|pair| pair[0].1 == pair[1].0));
+        assert_eq!((ranges[0].0, ranges[ranges.len() - 1].1), (10, 50));
+    }
+}
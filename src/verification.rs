@@ -0,0 +1,258 @@
+//! Speaker verification: decide whether two recordings have the same speaker.
+//!
+//! Similarity scores depend a lot on the extractor model, so a threshold should
+//! be calibrated for each model with [`calibrate`] on a labeled trial list rather
+//! than relying on [`DEFAULT_SIMILARITY_THRESHOLD`].
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use eyre::{bail, Result};
+
+use crate::{
+    read_audio_file,
+    speaker_id::{cosine_similarity, EmbeddingExtractor, DEFAULT_SIMILARITY_THRESHOLD},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Verification {
+    /// Cosine similarity of the two embeddings.
+    pub score: f32,
+    /// Whether the score reaches the threshold, i.e. the speakers are the same.
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Verifier {
+    extractor: Arc<EmbeddingExtractor>,
+    pub threshold: f32,
+}
+
+impl Verifier {
+    /// `threshold` defaults to [`DEFAULT_SIMILARITY_THRESHOLD`].
+    ///
+    /// Pass an `Arc` to share the loaded model, e.g. with an online tracker.
+    pub fn new(extractor: impl Into<Arc<EmbeddingExtractor>>, threshold: Option<f32>) -> Self {
+        Self {
+            extractor: extractor.into(),
+            threshold: threshold.unwrap_or(DEFAULT_SIMILARITY_THRESHOLD),
+        }
+    }
+
+    pub fn verify_embeddings(&self, a: &[f32], b: &[f32]) -> Verification {
+        let score = cosine_similarity(a, b);
+        Verification {
+            score,
+            accepted: score >= self.threshold,
+        }
+    }
+
+    pub fn verify_samples(&self, sample_rate: i32, a: &[f32], b: &[f32]) -> Result<Verification> {
+        let a = self.extractor.embed(sample_rate, a)?;
+        let b = self.extractor.embed(sample_rate, b)?;
+        Ok(self.verify_embeddings(&a, &b))
+    }
+
+    /// Compare two 16 kHz wav files.
+    pub fn verify_files(&self, a: &str, b: &str) -> Result<Verification> {
+        let (sample_rate, a) = read_audio_file(a)?;
+        let (b_sample_rate, b) = read_audio_file(b)?;
+        if sample_rate != b_sample_rate {
+            bail!("Sample rates differ: {} and {}", sample_rate, b_sample_rate)
+        }
+        self.verify_samples(sample_rate, &a, &b)
+    }
+
+    pub fn extractor(&self) -> &Arc<EmbeddingExtractor> {
+        &self.extractor
+    }
+
+    pub fn into_inner(self) -> Arc<EmbeddingExtractor> {
+        self.extractor
+    }
+}
+
+/// Score of a comparison with a known answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trial {
+    pub score: f32,
+    /// Whether both sides have the same speaker.
+    pub target: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// Equal error rate, where false acceptance and false rejection rates meet.
+    pub eer: f32,
+    pub eer_threshold: f32,
+    /// The false acceptance rate `far_threshold` was chosen for.
+    pub target_far: f32,
+    /// Lowest threshold whose false acceptance rate is at most `target_far`.
+    pub far_threshold: f32,
+    /// False rejection rate at `far_threshold`.
+    pub frr_at_far: f32,
+}
+
+/// Find the EER and the threshold for a false acceptance rate of `target_far`.
+///
+/// Needs at least one target and one non-target trial.
+pub fn calibrate(trials: &[Trial], target_far: f32) -> Result<Calibration> {
+    let targets = trials.iter().filter(|t| t.target).count();
+    let nontargets = trials.len() - targets;
+    if targets == 0 || nontargets == 0 {
+        bail!("Calibration needs both target and non-target trials")
+    }
+    if trials.iter().any(|t| t.score.is_nan()) {
+        bail!("Trial scores must not be NaN")
+    }
+
+    let mut sorted = trials.to_vec();
+    sorted.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    // Lower the threshold one distinct score at a time, starting with accepting nothing
+    let mut accepted_targets = 0;
+    let mut accepted_nontargets = 0;
+    let mut best_eer = (f32::INFINITY, 1.0, f32::INFINITY);
+    let mut far_threshold = (f32::INFINITY, 1.0);
+    let mut i = 0;
+    while i < sorted.len() {
+        let threshold = sorted[i].score;
+        while i < sorted.len() && sorted[i].score == threshold {
+            if sorted[i].target {
+                accepted_targets += 1;
+            } else {
+                accepted_nontargets += 1;
+            }
+            i += 1;
+        }
+        let far = accepted_nontargets as f32 / nontargets as f32;
+        let frr = 1.0 - accepted_targets as f32 / targets as f32;
+        if (far - frr).abs() < best_eer.0 {
+            best_eer = ((far - frr).abs(), (far + frr) / 2.0, threshold);
+        }
+        if far <= target_far {
+            far_threshold = (threshold, frr);
+        }
+    }
+
+    Ok(Calibration {
+        eer: best_eer.1,
+        eer_threshold: best_eer.2,
+        target_far,
+        far_threshold: far_threshold.0,
+        frr_at_far: far_threshold.1,
+    })
+}
+
+/// Score a trial list with lines like `1 enroll.wav test.wav`, where the first
+/// column is 1 (or `target`) for the same speaker and 0 (or `nontarget`) otherwise.
+///
+/// Relative paths are resolved against the directory of the list. Each file is
+/// embedded once, however many trials it appears in.
+pub fn score_trial_list<P: AsRef<Path>>(verifier: &Verifier, path: P) -> Result<Vec<Trial>> {
+    score_trials(path.as_ref(), |file| {
+        let (sample_rate, samples) = read_audio_file(&file.to_string_lossy())?;
+        verifier.extractor.embed(sample_rate, &samples)
+    })
+}
+
+fn score_trials<F>(path: &Path, mut embed: F) -> Result<Vec<Trial>>
+where
+    F: FnMut(&Path) -> Result<Vec<f32>>,
+{
+    let base = path.parent().unwrap_or(Path::new(""));
+    let reader = BufReader::new(File::open(path)?);
+    let mut embeddings: HashMap<PathBuf, Vec<f32>> = HashMap::new();
+    let mut trials = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() || fields[0].starts_with('#') {
+            continue;
+        }
+        let target = match fields.as_slice() {
+            ["1" | "target", _, _] => true,
+            ["0" | "nontarget", _, _] => false,
+            _ => bail!("Invalid trial on line {}: {}", number + 1, line),
+        };
+        for file in &fields[1..] {
+            if let Entry::Vacant(entry) = embeddings.entry(base.join(file)) {
+                let embedding = embed(entry.key())?;
+                entry.insert(embedding);
+            }
+        }
+        trials.push(Trial {
+            score: cosine_similarity(
+                &embeddings[&base.join(fields[1])],
+                &embeddings[&base.join(fields[2])],
+            ),
+            target,
+        });
+    }
+    Ok(trials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibrate() {
+        let scores = [
+            (0.9, true),
+            (0.8, true),
+            (0.7, false),
+            (0.6, true),
+            (0.5, false),
+            (0.4, false),
+            (0.3, true),
+            (0.2, false),
+        ];
+        let trials: Vec<Trial> = scores
+            .iter()
+            .map(|&(score, target)| Trial { score, target })
+            .collect();
+        let calibration = calibrate(&trials, 0.0).unwrap();
+        assert_eq!(calibration.eer, 0.25);
+        assert_eq!(calibration.eer_threshold, 0.6);
+        assert_eq!(calibration.far_threshold, 0.8);
+        assert_eq!(calibration.frr_at_far, 0.5);
+
+        assert!(calibrate(&trials[..2], 0.01).is_err());
+    }
+
+    #[test]
+    fn test_trial_list_embeds_each_file_once() {
+        let dir = std::env::temp_dir().join(format!("sherpa-rs-trials-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("trials.txt");
+        std::fs::write(
+            &list,
+            "# label enroll test\n1 a.wav a2.wav\n\n0 a.wav b.wav\ntarget b.wav b.wav\n",
+        )
+        .unwrap();
+
+        let mut embedded = Vec::new();
+        let trials = score_trials(&list, |file| {
+            let name = file.file_name().unwrap().to_string_lossy().to_string();
+            embedded.push(name.clone());
+            Ok(match name.as_str() {
+                "b.wav" => vec![0.0, 1.0],
+                _ => vec![1.0, 0.0],
+            })
+        })
+        .unwrap();
+        assert_eq!(embedded, vec!["a.wav", "a2.wav", "b.wav"]);
+        let scores: Vec<(f32, bool)> = trials.iter().map(|t| (t.score, t.target)).collect();
+        assert_eq!(scores, vec![(1.0, true), (0.0, false), (1.0, true)]);
+
+        std::fs::write(&list, "2 a.wav b.wav\n").unwrap();
+        assert!(score_trials(&list, |_| Ok(vec![1.0])).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}